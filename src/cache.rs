@@ -0,0 +1,224 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+
+use sha2::{Digest, Sha256};
+
+use gpu::SearchMode;
+
+/// Work sizes found by `--tune` for a device
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Profile {
+    pub threads: usize,
+    pub local_work_size: Option<usize>,
+    pub iterations: usize,
+}
+
+/// `$XDG_CACHE_HOME/algomania-gpu`, falling back to `~/.cache/algomania-gpu`
+pub fn cache_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(base.join("algomania-gpu"))
+}
+
+fn profiles_path() -> Option<PathBuf> {
+    cache_dir().map(|dir| dir.join("profiles"))
+}
+
+pub fn mode_name(mode: SearchMode) -> &'static str {
+    match mode {
+        SearchMode::Seed => "seed",
+        SearchMode::Incremental => "incremental",
+        SearchMode::Hd(_) => "hd",
+        SearchMode::LogicSig(_) => "logicsig",
+        SearchMode::Multisig(_) => "multisig",
+        SearchMode::SplitKey(_) => "split-key",
+        SearchMode::TxId(_) => "txid",
+    }
+}
+
+// One profile per line, tab separated:
+// device name, driver version, mode, threads, local work size or "-", iterations
+fn profile_key(device: &str, driver: &str, mode: SearchMode) -> String {
+    format!(
+        "{}\t{}\t{}",
+        device.replace('\t', " "),
+        driver.replace('\t', " "),
+        mode_name(mode)
+    )
+}
+
+fn format_line(device: &str, driver: &str, mode: SearchMode, profile: &Profile) -> String {
+    let local_work_size = match profile.local_work_size {
+        Some(size) => size.to_string(),
+        None => "-".to_string(),
+    };
+    format!(
+        "{}\t{}\t{}\t{}",
+        profile_key(device, driver, mode),
+        profile.threads,
+        local_work_size,
+        profile.iterations
+    )
+}
+
+fn has_key(line: &str, key: &str) -> bool {
+    line.starts_with(key) && line[key.len()..].starts_with('\t')
+}
+
+fn parse_line(line: &str, device: &str, driver: &str, mode: SearchMode) -> Option<Profile> {
+    if !has_key(line, &profile_key(device, driver, mode)) {
+        return None;
+    }
+    let fields: Vec<&str> = line.split('\t').collect();
+    if fields.len() != 6 {
+        return None;
+    }
+    let local_work_size = match fields[4] {
+        "-" => None,
+        size => Some(size.parse().ok()?),
+    };
+    Some(Profile {
+        threads: fields[3].parse().ok()?,
+        local_work_size,
+        iterations: fields[5].parse().ok()?,
+    })
+}
+
+fn find_profile(contents: &str, device: &str, driver: &str, mode: SearchMode) -> Option<Profile> {
+    contents
+        .lines()
+        .rev()
+        .find_map(|line| parse_line(line, device, driver, mode))
+}
+
+/// The tuned profile of a device, if `--tune` was run for it with the same
+/// driver version and search mode.
+pub fn load_profile(device: &str, driver: &str, mode: SearchMode) -> Option<Profile> {
+    let contents = fs::read_to_string(profiles_path()?).ok()?;
+    find_profile(&contents, device, driver, mode)
+}
+
+/// Stores a profile, replacing any previous one for the same device, driver
+/// version and search mode. Returns the path of the cache file.
+pub fn save_profile(
+    device: &str,
+    driver: &str,
+    mode: SearchMode,
+    profile: &Profile,
+) -> io::Result<PathBuf> {
+    let path = profiles_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No cache directory found"))?;
+    let key = profile_key(device, driver, mode);
+    let old = fs::read_to_string(&path).unwrap_or_default();
+    let mut contents = String::new();
+    for line in old.lines().filter(|line| !has_key(line, &key)) {
+        contents.push_str(line);
+        contents.push('\n');
+    }
+    contents.push_str(&format_line(device, driver, mode, profile));
+    contents.push('\n');
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, contents)?;
+    Ok(path)
+}
+
+fn programs_dir() -> Option<PathBuf> {
+    cache_dir().map(|dir| dir.join("programs"))
+}
+
+/// The cache key of a compiled program. Each part is length prefixed, so
+/// different splits of the same bytes don't collide.
+pub fn program_key(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// The program binary stored under `key`, if there is one
+pub fn load_program(key: &str) -> Option<Vec<u8>> {
+    let binary = fs::read(programs_dir()?.join(format!("{}.bin", key))).ok()?;
+    if binary.is_empty() {
+        return None;
+    }
+    Some(binary)
+}
+
+pub fn save_program(key: &str, binary: &[u8]) -> io::Result<()> {
+    let dir = programs_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No cache directory found"))?;
+    fs::create_dir_all(&dir)?;
+    // write to a temporary file first, so a concurrent start never loads
+    // half a binary
+    let tmp_path = dir.join(format!("{}.tmp{}", key, process::id()));
+    fs::write(&tmp_path, binary)?;
+    fs::rename(&tmp_path, dir.join(format!("{}.bin", key)))
+}
+
+/// Deletes all cached program binaries. Returns the directory they were in,
+/// if it existed.
+pub fn clear_programs() -> io::Result<Option<PathBuf>> {
+    let dir = match programs_dir() {
+        Some(dir) => dir,
+        None => return Ok(None),
+    };
+    match fs::remove_dir_all(&dir) {
+        Ok(()) => Ok(Some(dir)),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_roundtrip() {
+        let profile = Profile {
+            threads: 262144,
+            local_work_size: Some(64),
+            iterations: 16,
+        };
+        let other = Profile {
+            threads: 1024,
+            local_work_size: None,
+            iterations: 1,
+        };
+        let contents = format!(
+            "{}\n{}\n",
+            format_line("Radeon\tRX", "3.0", SearchMode::Seed, &other),
+            format_line("Radeon\tRX", "3.0", SearchMode::Incremental, &profile),
+        );
+        assert_eq!(
+            find_profile(&contents, "Radeon\tRX", "3.0", SearchMode::Incremental),
+            Some(profile)
+        );
+        assert_eq!(
+            find_profile(&contents, "Radeon\tRX", "3.0", SearchMode::Seed),
+            Some(other)
+        );
+        assert_eq!(
+            find_profile(&contents, "Radeon\tRX", "3.1", SearchMode::Seed),
+            None
+        );
+    }
+
+    #[test]
+    fn test_program_key() {
+        let key = program_key(&[b"source", b"-D X", b"device", b"driver"]);
+        assert_eq!(key.len(), 64);
+        assert_eq!(key, program_key(&[b"source", b"-D X", b"device", b"driver"]));
+        assert_ne!(key, program_key(&[b"source-D X", b"", b"device", b"driver"]));
+        assert_ne!(key, program_key(&[b"source", b"-D X", b"device", b"driver 2"]));
+    }
+}
@@ -0,0 +1,217 @@
+use num_traits::ToPrimitive;
+
+use derivation;
+use hd;
+use gpu::{Gpu, SearchMode, MAX_RESULTS};
+use pubkey_matcher::PubkeyMatcher;
+use split_key;
+
+/// The number of random matches a canary pattern is chosen to have per launch,
+/// on top of the planted one. Low enough that the results never overflow.
+const TARGET_HITS: usize = 4;
+
+/// The number of random matches expected over several canary launches before
+/// the observed hit rate is compared with it
+const MIN_EXPECTED_HITS: f64 = 64.0;
+
+/// A throwaway pattern with a match planted at a known offset of a launch
+pub struct Plant {
+    pub matcher: PubkeyMatcher,
+    pub offset: u64,
+}
+
+/// Decides when to run canary launches, and checks their results
+pub struct Canary {
+    interval: usize,
+    launches: usize,
+    hits: u64,
+    expected_hits: f64,
+}
+
+/// The public key a launch checks at `offset` from `root`, which is
+///
+/// - seed mode: the key material base
+/// - incremental and split-key mode: the start scalar
+/// - HD mode: the account and first index
+/// - logic signature mode: the nonce base
+/// - multisig mode: the key material base of the new member
+/// - transaction ID mode: the note nonce base
+///
+/// The "public key" of the last three is the address of the account or the ID.
+pub fn pubkey_at_offset(mode: SearchMode, root: &[u8; 32], offset: u64) -> [u8; 32] {
+    match mode {
+        SearchMode::Seed => {
+            derivation::ed25519_privkey_to_pubkey(&Gpu::seed_at_offset(root, offset))
+        }
+        SearchMode::Incremental => {
+            derivation::ed25519_scalar_to_pubkey(&derivation::incremental_scalar(root, offset))
+        }
+        SearchMode::Hd(ref hd_root) => {
+            let (account, first_index) = hd::parse_launch_root(root);
+            hd_root
+                .account_chain(account)
+                .child_pubkey(first_index + offset as u32)
+        }
+        SearchMode::LogicSig(template) => template.address(&template.nonce_at_offset(root, offset)),
+        SearchMode::SplitKey(ref pubkey) => {
+            let point = split_key::parse_point(pubkey).expect("Invalid split-key request");
+            split_key::offset_pubkey(&point, &derivation::incremental_scalar(root, offset))
+        }
+        SearchMode::Multisig(template) => template.address(&derivation::ed25519_privkey_to_pubkey(
+            &Gpu::seed_at_offset(root, offset),
+        )),
+        SearchMode::TxId(template) => template.id(&template.nonce_at_offset(root, offset)),
+    }
+}
+
+impl Canary {
+    /// Every `interval`th launch becomes a canary launch, none with 0
+    pub fn new(interval: usize) -> Canary {
+        Canary {
+            interval,
+            launches: 0,
+            hits: 0,
+            expected_hits: 0.0,
+        }
+    }
+
+    /// Whether the next launch should be a canary launch
+    pub fn due(&mut self) -> bool {
+        if self.interval == 0 {
+            return false;
+        }
+        self.launches += 1;
+        if self.launches < self.interval {
+            return false;
+        }
+        self.launches = 0;
+        true
+    }
+
+    /// Builds a pattern from the leading bits of the key at `offset`, with
+    /// as many bits as make about `TARGET_HITS` other keys of a launch match
+    pub fn plant(mode: SearchMode, root: &[u8; 32], keys_per_launch: usize, offset: u64) -> Plant {
+        let pubkey = pubkey_at_offset(mode, root, offset);
+        let mut bits: usize = 1;
+        while (keys_per_launch >> (bits + 1)) >= TARGET_HITS {
+            bits += 1;
+        }
+        let mut req = vec![0u8; 32];
+        let mut mask = vec![0u8; 32];
+        for (i, mask_byte) in mask.iter_mut().enumerate() {
+            let byte_bits = bits.saturating_sub(i * 8).min(8);
+            *mask_byte = !(0xffu8.checked_shr(byte_bits as u32).unwrap_or(0));
+            req[i] = pubkey[i] & *mask_byte;
+        }
+        Plant {
+            matcher: PubkeyMatcher::new(req, mask),
+            offset,
+        }
+    }
+
+    /// Checks that the planted match was found, that every other reported
+    /// match is real, and over time that the canary pattern matches about as
+    /// often as `estimated_attempts` says it should.
+    pub fn check(
+        &mut self,
+        mode: SearchMode,
+        root: &[u8; 32],
+        plant: &Plant,
+        offsets: &[u64],
+        keys_per_launch: usize,
+    ) -> Result<(), String> {
+        let overflowed = offsets.len() >= MAX_RESULTS;
+        if !overflowed && !offsets.contains(&plant.offset) {
+            return Err(format!(
+                "the planted match at offset {} was not found",
+                plant.offset
+            ));
+        }
+        for &offset in offsets.iter().filter(|&&offset| offset != plant.offset) {
+            if !plant.matcher.matches(&pubkey_at_offset(mode, root, offset)) {
+                return Err(format!(
+                    "the match reported at offset {} does not match the pattern",
+                    offset
+                ));
+            }
+        }
+        if overflowed {
+            return Ok(());
+        }
+
+        let attempts = plant
+            .matcher
+            .estimated_attempts()
+            .to_f64()
+            .unwrap_or(f64::INFINITY);
+        self.hits += offsets.len() as u64 - 1;
+        self.expected_hits += (keys_per_launch - 1) as f64 / attempts;
+        if self.expected_hits < MIN_EXPECTED_HITS {
+            return Ok(());
+        }
+        let hits = self.hits;
+        let expected_hits = self.expected_hits;
+        self.hits = 0;
+        self.expected_hits = 0.0;
+        let ratio = hits as f64 / expected_hits;
+        if !(0.5..=2.0).contains(&ratio) {
+            return Err(format!(
+                "{} random matches where about {:.0} were expected",
+                hits, expected_hits
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROOT: [u8; 32] = [7u8; 32];
+    const KEYS: usize = 64;
+
+    fn matching_offsets(plant: &Plant) -> Vec<u64> {
+        (0..KEYS as u64)
+            .filter(|&offset| {
+                plant
+                    .matcher
+                    .matches(&pubkey_at_offset(SearchMode::Seed, &ROOT, offset))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_planted_match() {
+        let plant = Canary::plant(SearchMode::Seed, &ROOT, KEYS, 42);
+        let offsets = matching_offsets(&plant);
+        assert!(offsets.contains(&42));
+        assert!(offsets.len() < MAX_RESULTS);
+
+        let mut canary = Canary::new(1);
+        assert!(canary
+            .check(SearchMode::Seed, &ROOT, &plant, &offsets, KEYS)
+            .is_ok());
+
+        let missing: Vec<u64> = offsets.iter().cloned().filter(|&o| o != 42).collect();
+        assert!(canary
+            .check(SearchMode::Seed, &ROOT, &plant, &missing, KEYS)
+            .is_err());
+
+        let bogus_offset = (0..KEYS as u64).find(|o| !offsets.contains(o)).unwrap();
+        let mut bogus = offsets.clone();
+        bogus.push(bogus_offset);
+        assert!(canary
+            .check(SearchMode::Seed, &ROOT, &plant, &bogus, KEYS)
+            .is_err());
+    }
+
+    #[test]
+    fn test_due() {
+        let mut canary = Canary::new(3);
+        let due: Vec<bool> = (0..6).map(|_| canary.due()).collect();
+        assert_eq!(due, [false, false, true, false, false, true]);
+        let mut disabled = Canary::new(0);
+        assert!((0..6).all(|_| !disabled.due()));
+    }
+}
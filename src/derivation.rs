@@ -1,8 +1,44 @@
+use curve25519_dalek::edwards::EdwardsPoint;
+use curve25519_dalek::scalar::Scalar;
+use data_encoding::BASE32_NOPAD;
 use ring::signature::{Ed25519KeyPair, KeyPair};
+use sha2::{Digest, Sha512, Sha512_256};
 use std::convert::TryInto;
 
 pub const ADDRESS_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
 
+/// The bytes of the SHA-512/256 of the public key appended to it in addresses
+const CHECKSUM_SIZE: usize = 4;
+
+fn address_checksum(pubkey: &[u8; 32]) -> [u8; CHECKSUM_SIZE] {
+    let mut checksum = [0u8; CHECKSUM_SIZE];
+    checksum.copy_from_slice(&Sha512_256::digest(pubkey)[32 - CHECKSUM_SIZE..]);
+    checksum
+}
+
+/// The 58 character address of a public key: base32 of the key followed by
+/// the last 4 bytes of its SHA-512/256, without padding
+pub fn pubkey_to_address(pubkey: &[u8; 32]) -> String {
+    let mut bytes = pubkey.to_vec();
+    bytes.extend_from_slice(&address_checksum(pubkey));
+    BASE32_NOPAD.encode(&bytes)
+}
+
+/// The public key of an address, if its checksum is right
+pub fn address_to_pubkey(address: &str) -> Result<[u8; 32], String> {
+    let bytes = BASE32_NOPAD
+        .decode(address.as_bytes())
+        .ok()
+        .filter(|bytes| bytes.len() == 32 + CHECKSUM_SIZE)
+        .ok_or_else(|| format!("{} is not an address", address))?;
+    let mut pubkey = [0u8; 32];
+    pubkey.copy_from_slice(&bytes[..32]);
+    if bytes[32..] != address_checksum(&pubkey) {
+        return Err(format!("The checksum of the address {} is wrong", address));
+    }
+    Ok(pubkey)
+}
+
 pub fn ed25519_privkey_to_pubkey(seed: &[u8; 32]) -> [u8; 32] {
     let key_pair = Ed25519KeyPair::from_seed_unchecked(seed).unwrap();
     let public_key = key_pair.public_key().as_ref();
@@ -10,92 +46,185 @@ pub fn ed25519_privkey_to_pubkey(seed: &[u8; 32]) -> [u8; 32] {
     public_key_byte_array
 }
 
+/// Derives the public keys of many seeds at once, giving the same results as
+/// `ed25519_privkey_to_pubkey`. The scalar multiplications use curve25519-dalek's
+/// precomputed basepoint table and all points are normalised with a single
+/// field inversion instead of building a `ring` key pair for every seed. Run
+/// with `--benchmark-cpu` to compare both on the current machine.
+pub fn ed25519_privkeys_to_pubkeys(seeds: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    let points: Vec<EdwardsPoint> = seeds
+        .iter()
+        .map(|seed| {
+            let hash = Sha512::digest(seed);
+            let mut scalar = [0u8; 32];
+            scalar.copy_from_slice(&hash[..32]);
+            EdwardsPoint::mul_base_clamped(scalar)
+        })
+        .collect();
+    EdwardsPoint::compress_batch_alloc(&points)
+        .into_iter()
+        .map(|point| point.to_bytes())
+        .collect()
+}
+
+/// Turns 32 random bytes into a start scalar for an incremental search. On top
+/// of the usual Ed25519 clamping bit 253 is cleared, so adding 8 * offset for
+/// any 64 bit offset keeps the scalar clamped.
+pub fn clamp_incremental_start(scalar: &mut [u8; 32]) {
+    scalar[0] &= 248;
+    scalar[31] &= 127;
+    scalar[31] |= 64;
+    scalar[31] &= !32;
+}
+
+/// The secret scalar of the key found at `offset` by the incremental kernel,
+/// which is start + 8 * offset.
+pub fn incremental_scalar(start: &[u8; 32], offset: u64) -> [u8; 32] {
+    let mut scalar = *start;
+    let mut carry = (offset as u128) << 3;
+    for byte in scalar.iter_mut() {
+        carry += *byte as u128;
+        *byte = carry as u8;
+        carry >>= 8;
+    }
+    debug_assert_eq!(carry, 0);
+    scalar
+}
+
+/// The public key of a secret scalar, the first half of an expanded key.
+pub fn ed25519_scalar_to_pubkey(scalar: &[u8; 32]) -> [u8; 32] {
+    EdwardsPoint::mul_base(&Scalar::from_bytes_mod_order(*scalar))
+        .compress()
+        .to_bytes()
+}
+
+/// Signs `msg` with a 64 byte expanded secret key, the secret scalar followed
+/// by the 32 byte prefix used to derive nonces. Keys derived from a seed give
+/// the same signatures as signing with the seed itself.
+pub fn ed25519_expanded_sign(expanded: &[u8; 64], msg: &[u8]) -> [u8; 64] {
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(&expanded[..32]);
+    let a = Scalar::from_bytes_mod_order(scalar);
+    let pubkey = EdwardsPoint::mul_base(&a).compress();
+
+    let mut hash = [0u8; 64];
+    hash.copy_from_slice(&Sha512::new().chain_update(&expanded[32..]).chain_update(msg).finalize());
+    let r = Scalar::from_bytes_mod_order_wide(&hash);
+    let big_r = EdwardsPoint::mul_base(&r).compress();
+
+    hash.copy_from_slice(
+        &Sha512::new()
+            .chain_update(big_r.as_bytes())
+            .chain_update(pubkey.as_bytes())
+            .chain_update(msg)
+            .finalize(),
+    );
+    let k = Scalar::from_bytes_mod_order_wide(&hash);
+    let s = r + k * a;
+
+    let mut signature = [0u8; 64];
+    signature[..32].copy_from_slice(big_r.as_bytes());
+    signature[32..].copy_from_slice(s.as_bytes());
+    signature
+}
+
 #[cfg(test)]
 mod tests {
     // importing names from outer (for mod tests) scope.
     use super::*;
 
     #[test]
-    fn test_ed25519_secret_to_pubkey() {
-        // generated by nanocurrency-js
-        // Seed: fb15ac405d762002202c66bd249589ad450d55631f7b1cd44fef19fcccbc6372
-        // Secret: 847B0EC950A7F5B6AD6C3A1AA5A5B940608435B59F201662D13A6D11F65F7DA6
-        // Pubkey: D741569435DC9698AAE5212A437F5DEDA76EFC4288CA3FCDE9604190A861FE07
-        // Address: nano_3ot3ctc5dq6pm4ogcabcafzouuf9fuy6748c9z8ykr43k4n85zi9zec5bxnz
-        let mut privkey = [0u8; 32];
-        privkey.copy_from_slice(
-            &hex::decode("847B0EC950A7F5B6AD6C3A1AA5A5B940608435B59F201662D13A6D11F65F7DA6")
-                .unwrap(),
-        );
-        let mut expected_pubkey = [0u8; 32];
-        expected_pubkey.copy_from_slice(
-            &hex::decode("D741569435DC9698AAE5212A437F5DEDA76EFC4288CA3FCDE9604190A861FE07")
-                .unwrap(),
+    fn test_batched_pubkeys_match_ring() {
+        assert!(ed25519_privkeys_to_pubkeys(&[]).is_empty());
+
+        let mut seeds = vec![[0u8; 32], [0xffu8; 32]];
+        for i in 0..254u8 {
+            let mut seed = [0u8; 32];
+            for (j, byte) in seed.iter_mut().enumerate() {
+                *byte = i.wrapping_mul(31).wrapping_add(j as u8 * 7);
+            }
+            seeds.push(seed);
+        }
+        let pubkeys = ed25519_privkeys_to_pubkeys(&seeds);
+        assert_eq!(pubkeys.len(), seeds.len());
+        for (seed, pubkey) in seeds.iter().zip(pubkeys.iter()) {
+            assert_eq!(*pubkey, ed25519_privkey_to_pubkey(seed));
+        }
+        assert_eq!(
+            ed25519_privkeys_to_pubkeys(&seeds[5..6]),
+            vec![ed25519_privkey_to_pubkey(&seeds[5])]
         );
-        assert_eq!(ed25519_privkey_to_pubkey(&privkey), expected_pubkey);
     }
 
     #[test]
-    fn test_secret_to_pubkey_from_privkey() {
-        // generated by nanocurrency-js
-        // Seed: fb15ac405d762002202c66bd249589ad450d55631f7b1cd44fef19fcccbc6372
-        // Secret: 847B0EC950A7F5B6AD6C3A1AA5A5B940608435B59F201662D13A6D11F65F7DA6
-        // Pubkey: D741569435DC9698AAE5212A437F5DEDA76EFC4288CA3FCDE9604190A861FE07
-        // Address: nano_3ot3ctc5dq6pm4ogcabcafzouuf9fuy6748c9z8ykr43k4n85zi9zec5bxnz
-        let mut privkey = [0u8; 32];
-        privkey.copy_from_slice(
-            &hex::decode("847B0EC950A7F5B6AD6C3A1AA5A5B940608435B59F201662D13A6D11F65F7DA6")
-                .unwrap(),
-        );
-        let mut expected_pubkey = [0u8; 32];
-        expected_pubkey.copy_from_slice(
-            &hex::decode("D741569435DC9698AAE5212A437F5DEDA76EFC4288CA3FCDE9604190A861FE07")
-                .unwrap(),
-        );
+    fn test_expanded_sign_matches_seed_sign() {
+        let seed = [0x42u8; 32];
+        let mut expanded = [0u8; 64];
+        expanded.copy_from_slice(&Sha512::digest(seed));
+        expanded[0] &= 248;
+        expanded[31] &= 127;
+        expanded[31] |= 64;
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&seed).unwrap();
+        let msg = b"algomania";
         assert_eq!(
-            secret_to_pubkey(privkey, GenerateKeyType::PrivateKey),
-            expected_pubkey
+            &ed25519_expanded_sign(&expanded, msg)[..],
+            key_pair.sign(msg).as_ref()
         );
     }
 
     #[test]
-    fn test_secret_to_pubkey_from_seed() {
-        // generated by nanocurrency-js
-        // Seed: fb15ac405d762002202c66bd249589ad450d55631f7b1cd44fef19fcccbc6372
-        // Secret: 847B0EC950A7F5B6AD6C3A1AA5A5B940608435B59F201662D13A6D11F65F7DA6
-        // Pubkey: D741569435DC9698AAE5212A437F5DEDA76EFC4288CA3FCDE9604190A861FE07
-        // Address: nano_3ot3ctc5dq6pm4ogcabcafzouuf9fuy6748c9z8ykr43k4n85zi9zec5bxnz
-        let mut seed = [0u8; 32];
-        seed.copy_from_slice(
-            &hex::decode("fb15ac405d762002202c66bd249589ad450d55631f7b1cd44fef19fcccbc6372")
-                .unwrap(),
-        );
-        let mut expected_pubkey = [0u8; 32];
-        expected_pubkey.copy_from_slice(
-            &hex::decode("D741569435DC9698AAE5212A437F5DEDA76EFC4288CA3FCDE9604190A861FE07")
-                .unwrap(),
-        );
+    fn test_incremental_scalar() {
+        let mut start = [0xffu8; 32];
+        clamp_incremental_start(&mut start);
+        let start_point = EdwardsPoint::mul_base(&Scalar::from_bytes_mod_order(start));
+        let step = EdwardsPoint::mul_base(&Scalar::from(8u8));
+        let offset = 0x1234_5678_9abc;
+        let scalar = incremental_scalar(&start, offset);
+        assert_eq!(scalar[0] & 7, 0);
+        assert_eq!(scalar[31] & 0xc0, 0x40);
         assert_eq!(
-            secret_to_pubkey(seed, GenerateKeyType::Seed),
-            expected_pubkey
+            ed25519_scalar_to_pubkey(&scalar),
+            (start_point + step * Scalar::from(offset)).compress().to_bytes()
         );
+
+        let mut expanded = [7u8; 64];
+        expanded[..32].copy_from_slice(&scalar);
+        let msg = b"algomania";
+        let signature = ed25519_expanded_sign(&expanded, msg);
+        let pubkey = ed25519_scalar_to_pubkey(&scalar);
+        ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, &pubkey[..])
+            .verify(msg, &signature)
+            .expect("Signature by expanded key does not verify");
+    }
+
+    // generated with algonaut's Account::from_seed, checked against a Python
+    // Ed25519 implementation
+    const ZERO_SEED_PUBKEY: &str = "3b6a27bcceb6a42d62a3a8d02a6f0d73653215771de243a63ac048a18b59da29";
+    const ZERO_SEED_ADDRESS: &str = "HNVCPPGOW2SC2YVDVDICU3YNONSTEFLXDXREHJR2YBEKDC2Z3IUZSC6YGI";
+    const ONES_SEED_PUBKEY: &str = "76a1592044a6e4f511265bca73a604d90b0529d1df602be30a19a9257660d1f5";
+    const ONES_SEED_ADDRESS: &str = "O2QVSICEU3SPKEJGLPFHHJQE3EFQKKOR35QCXYYKDGUSK5TA2H2Q3YZHOY";
+
+    #[test]
+    fn test_ed25519_secret_to_pubkey() {
+        assert_eq!(hex::encode(ed25519_privkey_to_pubkey(&[0u8; 32])), ZERO_SEED_PUBKEY);
+        assert_eq!(hex::encode(ed25519_privkey_to_pubkey(&[0xffu8; 32])), ONES_SEED_PUBKEY);
     }
 
     #[test]
     fn test_pubkey_to_address() {
-        // generated by nanocurrency-js
-        // Seed: fb15ac405d762002202c66bd249589ad450d55631f7b1cd44fef19fcccbc6372
-        // Secret: 847B0EC950A7F5B6AD6C3A1AA5A5B940608435B59F201662D13A6D11F65F7DA6
-        // Pubkey: D741569435DC9698AAE5212A437F5DEDA76EFC4288CA3FCDE9604190A861FE07
-        // Address: nano_3ot3ctc5dq6pm4ogcabcafzouuf9fuy6748c9z8ykr43k4n85zi9zec5bxnz
         let mut pubkey = [0u8; 32];
-        pubkey.copy_from_slice(
-            &hex::decode("D741569435DC9698AAE5212A437F5DEDA76EFC4288CA3FCDE9604190A861FE07")
-                .unwrap(),
-        );
-        assert_eq!(
-            pubkey_to_address(pubkey),
-            "nano_3ot3ctc5dq6pm4ogcabcafzouuf9fuy6748c9z8ykr43k4n85zi9zec5bxnz"
-        );
+        hex::decode_to_slice(ZERO_SEED_PUBKEY, &mut pubkey).unwrap();
+        assert_eq!(pubkey_to_address(&pubkey), ZERO_SEED_ADDRESS);
+        assert_eq!(address_to_pubkey(ZERO_SEED_ADDRESS), Ok(pubkey));
+        hex::decode_to_slice(ONES_SEED_PUBKEY, &mut pubkey).unwrap();
+        assert_eq!(pubkey_to_address(&pubkey), ONES_SEED_ADDRESS);
+        assert_eq!(address_to_pubkey(ONES_SEED_ADDRESS), Ok(pubkey));
+
+        // the well known address of the zero key
+        let zero = "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAY5HFKQ";
+        assert_eq!(pubkey_to_address(&[0u8; 32]), zero);
+        assert!(address_to_pubkey(&zero.replace("Y5HFKQ", "Y5HFKA")).is_err());
+        assert!(address_to_pubkey(&ZERO_SEED_ADDRESS[..57]).is_err());
+        assert!(address_to_pubkey(&ZERO_SEED_ADDRESS.to_lowercase()).is_err());
     }
 }
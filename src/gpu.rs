@@ -1,8 +1,58 @@
+use std::time::Duration;
+
+use hd;
+use logicsig;
+use multisig;
 use pubkey_matcher::PubkeyMatcher;
+use txid;
 
 #[cfg(feature = "gpu")]
-pub use gpu_impl::Gpu;
+pub use gpu_impl::{is_recoverable, Gpu, LaunchError, LaunchTimings, MAX_RESULTS};
+
+#[cfg(feature = "wgpu")]
+pub use wgpu_impl::WgpuGpu;
 
+/// How GPU threads come up with the keys they check.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SearchMode {
+    /// Every key is hashed from its own 32 byte seed, like a regular wallet
+    /// would, so found keys come with a mnemonic.
+    Seed,
+    /// Threads walk the points A + [8i]B from a random start scalar. Every key
+    /// only costs a point addition, but found keys are expanded secret keys
+    /// without a seed.
+    Incremental,
+    /// Threads derive the children m/44'/283'/account'/0/index of an ARC-0052
+    /// HD wallet, so found keys are covered by the wallet's backup.
+    Hd(hd::Root),
+    /// Threads vary the nonce of a logic signature, looking for a contract
+    /// account address instead of a public key.
+    LogicSig(&'static logicsig::Template),
+    /// Threads derive the key of a new multisig member from seeds, looking
+    /// for a multisig address instead of a public key.
+    Multisig(&'static multisig::Template),
+    /// An incremental search for offsets k of the client's compressed public
+    /// key P, such that P + [k]B matches. See `split_key`.
+    SplitKey([u8; 32]),
+    /// Threads vary a nonce in the note of an unsigned transaction, looking
+    /// for a transaction or group ID instead of a public key.
+    TxId(&'static txid::Template),
+}
+
+impl SearchMode {
+    /// The iterations per thread used unless the user asks for something
+    /// else. Incremental threads start with two scalar multiplications, which
+    /// only pay off over a longer walk.
+    pub fn default_iterations(self) -> usize {
+        match self {
+            SearchMode::Seed | SearchMode::Hd(_) | SearchMode::Multisig(_) => 1,
+            SearchMode::Incremental
+            | SearchMode::SplitKey(_)
+            | SearchMode::LogicSig(_)
+            | SearchMode::TxId(_) => 16,
+        }
+    }
+}
 
 #[derive(Clone, Copy)]
 pub struct GpuOptions<'a> {
@@ -12,4 +62,20 @@ pub struct GpuOptions<'a> {
     pub local_work_size: Option<usize>,
     pub global_work_size: Option<usize>,
     pub matcher: &'a PubkeyMatcher,
+    pub mode: SearchMode,
+    /// The number of keys every OpenCL work item packs with a single field
+    /// inversion
+    pub batch_size: usize,
+    /// The number of batches every OpenCL work item checks per launch
+    pub iterations: usize,
+    /// Replace threads, local_work_size and iterations with the values found
+    /// by `--tune` for this device, if there are any
+    pub use_tuned_profile: bool,
+    /// How long to wait for a launch before treating the device as hung
+    pub launch_timeout: Duration,
+    /// Enable OpenCL profiling to find out where the time of launches goes
+    pub profile: bool,
+    /// Overrides of the OpenCL features probed from the device, for drivers
+    /// that misreport them, e.g. `no-generic,printf`
+    pub opencl_features: Option<&'a str>,
 }
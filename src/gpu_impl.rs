@@ -1,40 +1,367 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::mem;
+use std::thread;
+use std::time::{Duration, Instant};
+
 use byteorder::ByteOrder;
 use byteorder::NativeEndian;
 use ocl;
 use ocl::builders::DeviceSpecifier;
 use ocl::builders::ProgramBuilder;
-use ocl::flags::MemFlags;
+use ocl::enums::{DeviceInfo, ProfilingInfo, ProgramInfo, ProgramInfoResult, Status};
+use ocl::flags::{CommandQueueProperties, MemFlags};
 use ocl::Buffer;
+use ocl::Context;
+use ocl::Device;
+use ocl::Event;
 use ocl::Platform;
 use ocl::ProQue;
+use ocl::Program;
+use ocl::Queue;
 use ocl::Result;
 
+use cache;
+use cache::Profile;
+use derivation;
 use gpu::GpuOptions;
+use gpu::SearchMode;
+use hd;
+use opencl_features::DeviceFeatures;
+use pubkey_matcher::PubkeyMatcher;
+use split_key;
+use txid;
 
-pub struct Gpu {
+/// The number of match offsets a single launch can return
+pub const MAX_RESULTS: usize = 64;
+
+/// The number of launches that can be queued on the device at once, so it
+/// never waits for the host between two of them
+const MAX_IN_FLIGHT: usize = 2;
+
+/// The size of the key root buffer, which fits the device root of every mode
+const KEY_ROOT_SIZE: usize = hd::DEVICE_ROOT_SIZE;
+
+/// The buffers of a single launch. The host side copies are boxed, because
+/// the device reads and writes them asynchronously and they must not move
+/// while a launch is in flight.
+struct Slot {
     kernel: ocl::Kernel,
-    result: Buffer<u64>,
+    /// The same kernel looking for the pattern of a canary launch instead
+    canary_kernel: ocl::Kernel,
+    canary: Pattern,
+    host_canary_req: Box<[u8; 32]>,
+    host_canary_mask: Box<[u8; 32]>,
     key_root: Buffer<u8>,
+    /// The number of matches and the number of threads that finished
+    counters: Buffer<u32>,
+    results: Buffer<u64>,
+    host_key_root: Box<[u8; KEY_ROOT_SIZE]>,
+    host_counters: Box<[u32; 2]>,
+    host_results: Box<[u64; MAX_RESULTS]>,
+    /// The keys every thread of the queued launch checks
+    keys_per_thread: usize,
+    kernel_event: Event,
+    transfer_events: Vec<Event>,
+    done: Event,
+}
+
+impl Slot {
+    /// Whether a transfer of the queued launch may still use the host buffers
+    fn is_busy(&self) -> bool {
+        self.transfer_events
+            .iter()
+            .chain(Some(&self.done))
+            .any(|event| !event.is_empty() && !event.is_complete().unwrap_or(false))
+    }
+}
+
+/// The results of a finished launch
+pub struct LaunchResults {
+    /// The offsets of all matches, see `Gpu::seed_at_offset` and
+    /// `derivation::incremental_scalar`
+    pub offsets: Vec<u64>,
+    /// The number of keys the kernel reported as checked
+    pub keys_checked: usize,
+}
+
+/// Where the device time of launches went, from OpenCL profiling events
+#[derive(Clone, Copy, Default, Debug)]
+pub struct LaunchTimings {
+    pub launches: usize,
+    pub kernel: Duration,
+    pub transfers: Duration,
+}
+
+/// The buffers of the pattern a kernel looks for
+struct Pattern {
+    req: Buffer<u8>,
+    mask: Buffer<u8>,
+    prefix_len: u8,
+    /// The client's public key in split-key mode, the identity otherwise
+    public_offset: Buffer<u8>,
+    /// The message hashed into the address in logic signature and multisig
+    /// mode or into the ID in transaction ID mode, a single byte in the
+    /// other modes
+    message: Buffer<u8>,
+}
+
+pub struct Gpu {
+    pro_que: ProQue,
+    queue: Queue,
+    mode: SearchMode,
+    pattern: Pattern,
+    slots: Vec<Slot>,
+    /// Indices into `slots` of the queued launches, oldest first
+    in_flight: VecDeque<usize>,
+    work_size: Profile,
+    batch_size: usize,
+    launch_timeout: Duration,
+    /// Collected when profiling is enabled, see `take_timings`
+    timings: Option<LaunchTimings>,
+    device_name: String,
+    driver_version: String,
+}
+
+static ZERO_COUNTERS: [u32; 2] = [0, 0];
+
+/// Why a launch failed
+#[derive(Debug)]
+pub enum LaunchError {
+    /// The launch did not complete within the launch timeout
+    Timeout(Duration),
+    Ocl(ocl::Error),
+}
+
+impl From<ocl::Error> for LaunchError {
+    fn from(err: ocl::Error) -> LaunchError {
+        LaunchError::Ocl(err)
+    }
+}
+
+impl From<LaunchError> for ocl::Error {
+    fn from(err: LaunchError) -> ocl::Error {
+        match err {
+            LaunchError::Ocl(err) => err,
+            err => err.to_string().into(),
+        }
+    }
+}
+
+impl fmt::Display for LaunchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LaunchError::Timeout(timeout) => write!(
+                f,
+                "the launch did not complete within {} seconds",
+                timeout.as_secs_f64()
+            ),
+            LaunchError::Ocl(ref err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl LaunchError {
+    /// Whether rebuilding the context may help, see `is_recoverable`. A hung
+    /// launch usually means the driver reset the device.
+    pub fn recoverable(&self) -> bool {
+        match *self {
+            LaunchError::Timeout(_) => true,
+            LaunchError::Ocl(ref err) => is_recoverable(err),
+        }
+    }
+}
+
+/// Whether an OpenCL error comes from the device or driver getting into a bad
+/// state, which a fresh context may fix. Anything else, like a failed build,
+/// invalid arguments or a failed self-test, would only happen again.
+pub fn is_recoverable(err: &ocl::Error) -> bool {
+    matches!(
+        err.api_status(),
+        Some(Status::CL_DEVICE_NOT_AVAILABLE)
+            | Some(Status::CL_MEM_OBJECT_ALLOCATION_FAILURE)
+            | Some(Status::CL_OUT_OF_RESOURCES)
+            | Some(Status::CL_OUT_OF_HOST_MEMORY)
+            | Some(Status::CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST)
+            | Some(Status::CL_INVALID_CONTEXT)
+            | Some(Status::CL_INVALID_COMMAND_QUEUE)
+            | Some(Status::CL_INVALID_EVENT)
+    )
+}
+
+/// The key root of the self-test. The first 8 bytes, where nonces vary, and
+/// the next 8, where seeds vary, wrap around within the launch, like they may
+/// in a search.
+const SELF_TEST_KEY_ROOT: [u8; 32] = [
+    0xfc, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfb, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xd0, 0x27, 0x93, 0x4e, 0xb5, 0x71, 0x0a, 0xe8, 0x36, 0xcb, 0x52, 0x8f, 0x64, 0x1d, 0xf9, 0x80,
+];
+const SELF_TEST_THREADS: usize = 4;
+const SELF_TEST_ITERATIONS: usize = 2;
+
+fn build_kernel(
+    pro_que: &ProQue,
+    mode: SearchMode,
+    pattern: &Pattern,
+    slot: (&Buffer<u32>, &Buffer<u64>, &Buffer<u8>),
+    work_size: &Profile,
+) -> Result<ocl::Kernel> {
+    let (counters, results, key_root) = slot;
+    if work_size.iterations == 0 || work_size.iterations > u32::MAX as usize {
+        return Err(format!("Invalid iterations per thread: {}", work_size.iterations).into());
+    }
+    let mut kernel_builder;
+    match mode {
+        SearchMode::Seed => {
+            kernel_builder = pro_que.kernel_builder("generate_pubkey");
+            kernel_builder
+                .arg(counters)
+                .arg(results)
+                .arg(key_root)
+                .arg(&pattern.req)
+                .arg(&pattern.mask)
+                .arg(pattern.prefix_len)
+                .arg(work_size.iterations as u32);
+        }
+        SearchMode::Incremental | SearchMode::SplitKey(_) => {
+            // key_root holds the compressed start point
+            kernel_builder = pro_que.kernel_builder("generate_pubkey_incremental");
+            kernel_builder
+                .arg(counters)
+                .arg(results)
+                .arg(key_root)
+                .arg(&pattern.req)
+                .arg(&pattern.mask)
+                .arg(pattern.prefix_len)
+                .arg(&pattern.public_offset)
+                .arg(work_size.iterations as u32);
+        }
+        SearchMode::Hd(_) => {
+            // key_root holds the chain of the account, see hd::DEVICE_ROOT_SIZE
+            kernel_builder = pro_que.kernel_builder("generate_pubkey_hd");
+            kernel_builder
+                .arg(counters)
+                .arg(results)
+                .arg(key_root)
+                .arg(&pattern.req)
+                .arg(&pattern.mask)
+                .arg(pattern.prefix_len)
+                .arg(work_size.iterations as u32);
+        }
+        SearchMode::LogicSig(template) => {
+            // key_root holds the nonce base
+            kernel_builder = pro_que.kernel_builder("generate_logicsig");
+            kernel_builder
+                .arg(counters)
+                .arg(results)
+                .arg(key_root)
+                .arg(&pattern.message)
+                .arg(template.message().len() as u32)
+                .arg(template.nonce_pos() as u32)
+                .arg(template.nonce_len() as u8)
+                .arg(&pattern.req)
+                .arg(&pattern.mask)
+                .arg(pattern.prefix_len)
+                .arg(work_size.iterations as u32);
+        }
+        SearchMode::Multisig(template) => {
+            // key_root holds the key material base of the new member
+            kernel_builder = pro_que.kernel_builder("generate_multisig");
+            kernel_builder
+                .arg(counters)
+                .arg(results)
+                .arg(key_root)
+                .arg(&pattern.message)
+                .arg(template.message().len() as u32)
+                .arg(template.member_pos() as u32)
+                .arg(&pattern.req)
+                .arg(&pattern.mask)
+                .arg(pattern.prefix_len)
+                .arg(work_size.iterations as u32);
+        }
+        SearchMode::TxId(template) => {
+            // key_root holds the nonce base
+            kernel_builder = pro_que.kernel_builder("generate_txid");
+            kernel_builder
+                .arg(counters)
+                .arg(results)
+                .arg(key_root)
+                .arg(&pattern.message)
+                .arg(template.tx_len() as u32)
+                .arg(template.nonce_pos() as u32)
+                .arg(txid::NONCE_SIZE as u8)
+                .arg(template.group_len() as u32)
+                .arg(template.group_pos() as u32)
+                .arg(&pattern.req)
+                .arg(&pattern.mask)
+                .arg(pattern.prefix_len)
+                .arg(work_size.iterations as u32);
+        }
+    }
+    kernel_builder.global_work_size(work_size.threads);
+    if let Some(local_work_size) = work_size.local_work_size {
+        kernel_builder.local_work_size(local_work_size);
+    }
+    kernel_builder.build()
+}
+
+/// Builds the program for `device`, loading the binary compiled by an earlier
+/// run with the same source, options, device and driver when there is one
+fn build_program(
+    prog_bldr: &mut ProgramBuilder,
+    context: &Context,
+    device: Device,
+    driver_version: &str,
+) -> Result<Program> {
+    prog_bldr.devices(device);
+    let mut source = Vec::new();
+    for src in prog_bldr.get_src_strings()? {
+        source.extend_from_slice(src.as_bytes());
+    }
+    let options = prog_bldr.get_compiler_options()?;
+    let key = cache::program_key(&[
+        &source,
+        options.as_bytes(),
+        device.vendor()?.as_bytes(),
+        device.name()?.as_bytes(),
+        driver_version.as_bytes(),
+    ]);
+
+    if let Some(binary) = cache::load_program(&key) {
+        let binaries = [&binary[..]];
+        let program = ProgramBuilder::new()
+            .binaries(&binaries)
+            .devices(device)
+            .build(context);
+        match program {
+            Ok(program) => return Ok(program),
+            Err(err) => eprintln!("Ignoring cached OpenCL program: {}", err),
+        }
+    }
+
+    let program = prog_bldr.build(context)?;
+    if let ProgramInfoResult::Binaries(binaries) = program.info(ProgramInfo::Binaries)? {
+        if let Some(binary) = binaries.first() {
+            if let Err(err) = cache::save_program(&key, binary) {
+                eprintln!("Failed to cache the OpenCL program: {}", err);
+            }
+        }
+    }
+    Ok(program)
+}
+
+/// The time between the start and end of a command on the device
+fn event_duration(event: &Event) -> Result<Duration> {
+    let start = event.profiling_info(ProfilingInfo::Start)?.time()?;
+    let end = event.profiling_info(ProfilingInfo::End)?.time()?;
+    Ok(Duration::from_nanos(end.saturating_sub(start)))
 }
 
 impl Gpu {
     pub fn new(opts: GpuOptions) -> Result<Gpu> {
-        let mut prog_bldr = ProgramBuilder::new();
-        let namespace_qualifier = if cfg!(feature = "apple") {
-            "#define NAMESPACE_QUALIFIER __private\n"
-        } else {
-            "#define NAMESPACE_QUALIFIER __generic\n"
-        };
-        prog_bldr
-            .source(namespace_qualifier)
-            .src(include_str!("opencl/types.cl"))
-            .src(include_str!("opencl/curve25519-constants.cl"))
-            .src(include_str!("opencl/curve25519-constants2.cl"))
-            .src(include_str!("opencl/curve25519.cl"))
-            .src(include_str!("opencl/sha/inc_hash_functions.cl"))
-            .src(include_str!("opencl/sha/sha512.cl"))
-            .src(include_str!("opencl/sha_bindings.cl"))
-            .src(include_str!("opencl/entry.cl"));
+        if opts.batch_size == 0 {
+            return Err("The batch size must be at least 1".into());
+        }
         let platforms = Platform::list();
         if platforms.len() == 0 {
             return Err("No OpenCL platforms exist (check your drivers and OpenCL setup)".into());
@@ -47,25 +374,70 @@ impl Gpu {
             )
             .into());
         }
-        let mut pro_que = ProQue::builder()
-            .prog_bldr(prog_bldr)
-            .platform(platforms[opts.platform_idx])
-            .device(DeviceSpecifier::Indices(vec![opts.device_idx]))
-            .dims(1)
-            .build()?;
+        let platform = platforms[opts.platform_idx];
+        let device = *DeviceSpecifier::Indices(vec![opts.device_idx])
+            .to_device_list(Some(platform))?
+            .first()
+            .ok_or("No such OpenCL device")?;
+        let device_name = device.name()?;
+        let driver_version = device.info(DeviceInfo::DriverVersion)?.to_string();
+        eprintln!("Initializing GPU {} {}", device.vendor()?, device_name);
+        let mut features = DeviceFeatures::probe(device)?;
+        if let Some(overrides) = opts.opencl_features {
+            features.apply_overrides(overrides)?;
+        }
+        eprintln!("Compiling for {}", features);
 
-        let device = pro_que.device();
-        eprintln!("Initializing GPU {} {}", device.vendor()?, device.name()?);
+        let mut prog_bldr = ProgramBuilder::new();
+        prog_bldr
+            .source(features.defines())
+            .source(format!("#define BATCH_SIZE {}\n", opts.batch_size))
+            .source(format!("#define MAX_RESULTS {}\n", MAX_RESULTS))
+            .src(include_str!("opencl/types.cl"))
+            .src(include_str!("opencl/curve25519-constants.cl"))
+            .src(include_str!("opencl/curve25519-constants2.cl"))
+            .src(include_str!("opencl/curve25519.cl"))
+            .src(include_str!("opencl/sha/inc_hash_functions.cl"))
+            .src(include_str!("opencl/sha/sha512.cl"))
+            .src(include_str!("opencl/sha_bindings.cl"))
+            .src(include_str!("opencl/entry.cl"));
+        if let Some(options) = features.compiler_options() {
+            prog_bldr.cmplr_opt(options);
+        }
 
-        let result = pro_que
-            .buffer_builder::<u64>()
-            .flags(MemFlags::new().write_only())
-            .build()?;
-        pro_que.set_dims(64);
-        let key_root = pro_que
-            .buffer_builder::<u8>()
-            .flags(MemFlags::new().read_only().host_write_only())
+        let context = Context::builder()
+            .platform(platform)
+            .devices(device)
             .build()?;
+        let queue_properties = if opts.profile {
+            Some(CommandQueueProperties::new().profiling())
+        } else {
+            None
+        };
+        let queue = Queue::new(&context, device, queue_properties)?;
+        let program = build_program(&mut prog_bldr, &context, device, &driver_version)?;
+        let mut pro_que = ProQue::new(context, queue, program, Some(1));
+
+        let mut work_size = Profile {
+            threads: opts.global_work_size.unwrap_or(opts.threads),
+            local_work_size: opts.local_work_size,
+            iterations: opts.iterations,
+        };
+        if opts.use_tuned_profile {
+            if let Some(profile) = cache::load_profile(&device_name, &driver_version, opts.mode) {
+                eprintln!(
+                    "Using tuned work sizes: {} threads, local work size {}, {} iterations per thread",
+                    profile.threads,
+                    profile
+                        .local_work_size
+                        .map(|size| size.to_string())
+                        .unwrap_or_else(|| "auto".to_string()),
+                    profile.iterations,
+                );
+                work_size = profile;
+            }
+        }
+
         pro_que.set_dims(opts.matcher.prefix_len());
         let req = pro_que
             .buffer_builder::<u8>()
@@ -80,69 +452,614 @@ impl Gpu {
             .buffer_builder::<u8>()
             .flags(MemFlags::new().read_only().host_write_only())
             .build()?;
-        pro_que.set_dims(1);
+
+        let message = match opts.mode {
+            SearchMode::LogicSig(template) => template.message(),
+            SearchMode::Multisig(template) => template.message(),
+            SearchMode::TxId(template) => template.message(),
+            _ => &[0u8],
+        };
+        let message_buffer = pro_que
+            .buffer_builder::<u8>()
+            .len(message.len())
+            .flags(MemFlags::new().read_only().host_write_only())
+            .build()?;
 
         req.write(opts.matcher.req()).enq()?;
         mask.write(opts.matcher.mask()).enq()?;
-        result.write(&[!0u64] as &[u64]).enq()?;
-
-        let kernel = {
-            let mut kernel_builder = pro_que.kernel_builder("generate_pubkey");
-            kernel_builder
-                .global_work_size(opts.threads)
-                .arg(&result)
-                .arg(&key_root)
-                .arg(&req)
-                .arg(&mask)
-                .arg(opts.matcher.prefix_len() as u8)
-                .arg(&public_offset);
-            if let Some(local_work_size) = opts.local_work_size {
-                kernel_builder.local_work_size(local_work_size);
-            }
-            if let Some(global_work_size) = opts.global_work_size {
-                kernel_builder.global_work_size(global_work_size);
-            }
-            kernel_builder.build()?
+        match opts.mode {
+            SearchMode::SplitKey(ref pubkey) => public_offset.write(&pubkey[..]).enq()?,
+            _ => public_offset.write(&split_key::IDENTITY[..]).enq()?,
+        }
+        message_buffer.write(message).enq()?;
+        let pattern = Pattern {
+            req,
+            mask,
+            prefix_len: opts.matcher.prefix_len() as u8,
+            public_offset,
+            message: message_buffer,
         };
 
-        Ok(Gpu {
-            kernel,
-            result,
-            key_root,
-        })
+        let mut slots = Vec::with_capacity(MAX_IN_FLIGHT);
+        for _ in 0..MAX_IN_FLIGHT {
+            pro_que.set_dims(2);
+            let counters = pro_que
+                .buffer_builder::<u32>()
+                .flags(MemFlags::new().read_write())
+                .build()?;
+            pro_que.set_dims(MAX_RESULTS);
+            let results = pro_que
+                .buffer_builder::<u64>()
+                .flags(MemFlags::new().write_only())
+                .build()?;
+            pro_que.set_dims(KEY_ROOT_SIZE);
+            let key_root = pro_que
+                .buffer_builder::<u8>()
+                .flags(MemFlags::new().read_only().host_write_only())
+                .build()?;
+
+            pro_que.set_dims(32);
+            let canary = Pattern {
+                req: pro_que
+                    .buffer_builder::<u8>()
+                    .flags(MemFlags::new().read_only().host_write_only())
+                    .build()?,
+                mask: pro_que
+                    .buffer_builder::<u8>()
+                    .flags(MemFlags::new().read_only().host_write_only())
+                    .build()?,
+                prefix_len: 32,
+                public_offset: pattern.public_offset.clone(),
+                message: pattern.message.clone(),
+            };
+
+
+            let kernel = build_kernel(
+                &pro_que,
+                opts.mode,
+                &pattern,
+                (&counters, &results, &key_root),
+                &work_size,
+            )?;
+            let canary_kernel = build_kernel(
+                &pro_que,
+                opts.mode,
+                &canary,
+                (&counters, &results, &key_root),
+                &work_size,
+            )?;
+
+            slots.push(Slot {
+                kernel,
+                canary_kernel,
+                canary,
+                host_canary_req: Box::new([0u8; 32]),
+                host_canary_mask: Box::new([0u8; 32]),
+                key_root,
+                counters,
+                results,
+                host_key_root: Box::new([0u8; KEY_ROOT_SIZE]),
+                host_counters: Box::new([0u32; 2]),
+                host_results: Box::new([0u64; MAX_RESULTS]),
+                keys_per_thread: 0,
+                kernel_event: Event::empty(),
+                transfer_events: Vec::with_capacity(5),
+                done: Event::empty(),
+            });
+        }
+
+        let gpu = Gpu {
+            queue: pro_que.queue().clone(),
+            pro_que,
+            mode: opts.mode,
+            pattern,
+            slots,
+            in_flight: VecDeque::with_capacity(MAX_IN_FLIGHT),
+            work_size,
+            batch_size: opts.batch_size,
+            launch_timeout: opts.launch_timeout,
+            timings: if opts.profile {
+                Some(LaunchTimings::default())
+            } else {
+                None
+            },
+            device_name,
+            driver_version,
+        };
+        gpu.self_test()?;
+        Ok(gpu)
     }
 
-    pub fn compute(&mut self, out: &mut [u8], key_root: &[u8]) -> Result<bool> {
-        Self::yes(key_root, false);
-        self.key_root.write(key_root).enq()?;
-        debug_assert!(out.iter().all(|&b| b == 0));
-        debug_assert!({
-            let mut result = [0u64];
-            self.result.read(&mut result as &mut [u64]).enq()?;
-            result == [!0u64]
-        });
+    /// Derives the public keys of a small launch from a fixed key root with
+    /// the same code as the search kernel, and compares them with the CPU
+    /// derivation. Every mismatch is printed with its diverging bytes.
+    fn self_test(&self) -> Result<()> {
+        let keys = SELF_TEST_THREADS * SELF_TEST_ITERATIONS * self.batch_size;
+        let pubkeys = self
+            .pro_que
+            .buffer_builder::<u8>()
+            .len(keys * 32)
+            .flags(MemFlags::new().write_only())
+            .build()?;
+        let key_root = self
+            .pro_que
+            .buffer_builder::<u8>()
+            .len(KEY_ROOT_SIZE)
+            .flags(MemFlags::new().read_only().host_write_only())
+            .build()?;
+
+        let mut start_scalar = SELF_TEST_KEY_ROOT;
+        derivation::clamp_incremental_start(&mut start_scalar);
+        // an account chain with the keys right below the hardened indices,
+        // using the derivation of the searched wallet
+        let hd_derivation = match self.mode {
+            SearchMode::Hd(ref root) => root.derivation(),
+            _ => hd::Derivation::Peikert,
+        };
+        let chain = hd::Chain::new(
+            derivation::ed25519_scalar_to_pubkey(&start_scalar),
+            SELF_TEST_KEY_ROOT,
+            hd_derivation,
+        )
+        .ok_or("Invalid self-test chain")?;
+        let first_index = hd::HARDENED - keys as u32;
+        let mut root = [0u8; KEY_ROOT_SIZE];
+        let kernel_name = match self.mode {
+            SearchMode::Seed => {
+                root[..32].copy_from_slice(&SELF_TEST_KEY_ROOT);
+                "self_test_pubkeys"
+            }
+            SearchMode::Incremental | SearchMode::SplitKey(_) => {
+                root[..32].copy_from_slice(&derivation::ed25519_scalar_to_pubkey(&start_scalar));
+                "self_test_pubkeys_incremental"
+            }
+            SearchMode::Hd(_) => {
+                root.copy_from_slice(&chain.device_root(first_index));
+                "self_test_pubkeys_hd"
+            }
+            SearchMode::LogicSig(_) => {
+                root[..32].copy_from_slice(&SELF_TEST_KEY_ROOT);
+                "self_test_logicsig"
+            }
+            SearchMode::Multisig(_) => {
+                root[..32].copy_from_slice(&SELF_TEST_KEY_ROOT);
+                "self_test_multisig"
+            }
+            SearchMode::TxId(_) => {
+                root[..32].copy_from_slice(&SELF_TEST_KEY_ROOT);
+                "self_test_txid"
+            }
+        };
+        key_root.write(&root[..]).enq()?;
+        let mut kernel_builder = self.pro_que.kernel_builder(kernel_name);
+        kernel_builder
+            .arg(&pubkeys)
+            .arg(&key_root)
+            .arg(SELF_TEST_ITERATIONS as u32)
+            .global_work_size(SELF_TEST_THREADS);
+        match self.mode {
+            SearchMode::Incremental | SearchMode::SplitKey(_) => {
+                kernel_builder.arg(&self.pattern.public_offset);
+            }
+            SearchMode::LogicSig(template) => {
+                kernel_builder
+                    .arg(&self.pattern.message)
+                    .arg(template.message().len() as u32)
+                    .arg(template.nonce_pos() as u32)
+                    .arg(template.nonce_len() as u8);
+            }
+            SearchMode::Multisig(template) => {
+                kernel_builder
+                    .arg(&self.pattern.message)
+                    .arg(template.message().len() as u32)
+                    .arg(template.member_pos() as u32);
+            }
+            SearchMode::TxId(template) => {
+                kernel_builder
+                    .arg(&self.pattern.message)
+                    .arg(template.tx_len() as u32)
+                    .arg(template.nonce_pos() as u32)
+                    .arg(txid::NONCE_SIZE as u8)
+                    .arg(template.group_len() as u32)
+                    .arg(template.group_pos() as u32);
+            }
+            SearchMode::Seed | SearchMode::Hd(_) => {}
+        }
+        let kernel = kernel_builder.build()?;
+        unsafe {
+            kernel.enq()?;
+        }
+        let mut gpu_pubkeys = vec![0u8; keys * 32];
+        pubkeys.read(&mut gpu_pubkeys).enq()?;
+
+        let mut failures = 0;
+        for (offset, gpu_pubkey) in gpu_pubkeys.chunks(32).enumerate() {
+            let (secret, expected) = match self.mode {
+                SearchMode::Seed => {
+                    let seed = Gpu::seed_at_offset(&SELF_TEST_KEY_ROOT, offset as u64);
+                    (hex::encode(seed), derivation::ed25519_privkey_to_pubkey(&seed))
+                }
+                SearchMode::Incremental => {
+                    let scalar = derivation::incremental_scalar(&start_scalar, offset as u64);
+                    (hex::encode(scalar), derivation::ed25519_scalar_to_pubkey(&scalar))
+                }
+                SearchMode::SplitKey(ref pubkey) => {
+                    let point = split_key::parse_point(pubkey)?;
+                    let scalar = derivation::incremental_scalar(&start_scalar, offset as u64);
+                    (hex::encode(scalar), split_key::offset_pubkey(&point, &scalar))
+                }
+                SearchMode::Hd(_) => {
+                    let index = first_index + offset as u32;
+                    (format!("child index {}", index), chain.child_pubkey(index))
+                }
+                SearchMode::LogicSig(template) => {
+                    let nonce = template.nonce_at_offset(&SELF_TEST_KEY_ROOT, offset as u64);
+                    (format!("nonce {}", hex::encode(&nonce)), template.address(&nonce))
+                }
+                SearchMode::Multisig(template) => {
+                    let seed = Gpu::seed_at_offset(&SELF_TEST_KEY_ROOT, offset as u64);
+                    let pubkey = derivation::ed25519_privkey_to_pubkey(&seed);
+                    (hex::encode(seed), template.address(&pubkey))
+                }
+                SearchMode::TxId(template) => {
+                    let nonce = template.nonce_at_offset(&SELF_TEST_KEY_ROOT, offset as u64);
+                    (format!("nonce {}", hex::encode(&nonce)), template.id(&nonce))
+                }
+            };
+            if gpu_pubkey == &expected[..] {
+                continue;
+            }
+            failures += 1;
+            let diverging: Vec<String> = (0..32)
+                .filter(|&i| gpu_pubkey[i] != expected[i])
+                .map(|i| i.to_string())
+                .collect();
+            eprintln!("Self-test mismatch at offset {}:", offset);
+            eprintln!("  secret:   {}", secret);
+            eprintln!("  expected: {}", hex::encode(expected));
+            eprintln!("  GPU:      {}", hex::encode(gpu_pubkey));
+            eprintln!("  diverging bytes: {}", diverging.join(", "));
+        }
+        if failures != 0 {
+            return Err(format!(
+                "GPU self-test failed for {} of {} keys, refusing to use this device",
+                failures, keys
+            )
+            .into());
+        }
+        if let SearchMode::Hd(ref root) = self.mode {
+            self.self_test_hd_reference(root.derivation())?;
+        }
+        Ok(())
+    }
 
+    /// Derives the first keys of the ARC-0052 reference wallet with the HD
+    /// kernel, so a device that agrees with the CPU derivation also has to
+    /// agree with real wallets
+    fn self_test_hd_reference(&self, hd_derivation: hd::Derivation) -> Result<()> {
+        let expected = hd::reference_pubkeys(hd_derivation);
+        let keys = self.batch_size;
+        let pubkeys = self
+            .pro_que
+            .buffer_builder::<u8>()
+            .len(keys * 32)
+            .flags(MemFlags::new().write_only())
+            .build()?;
+        let root = hd::Root::from_mnemonic(hd::REFERENCE_MNEMONIC, "", hd_derivation)?;
+        let key_root = self
+            .pro_que
+            .buffer_builder::<u8>()
+            .len(KEY_ROOT_SIZE)
+            .flags(MemFlags::new().read_only().host_write_only())
+            .build()?;
+        key_root.write(&root.device_root(0, 0)[..]).enq()?;
+        let kernel = self
+            .pro_que
+            .kernel_builder("self_test_pubkeys_hd")
+            .arg(&pubkeys)
+            .arg(&key_root)
+            .arg(1u32)
+            .global_work_size(1)
+            .build()?;
         unsafe {
-            self.kernel.enq()?;
+            kernel.enq()?;
+        }
+        let mut gpu_pubkeys = vec![0u8; keys * 32];
+        pubkeys.read(&mut gpu_pubkeys).enq()?;
+        for (index, (gpu_pubkey, expected)) in gpu_pubkeys.chunks(32).zip(expected.iter()).enumerate() {
+            if hex::encode(gpu_pubkey) != *expected {
+                eprintln!("Self-test mismatch at {}:", hd::path(0, index as u32));
+                eprintln!("  expected: {}", expected);
+                eprintln!("  GPU:      {}", hex::encode(gpu_pubkey));
+                return Err("GPU self-test failed for the ARC-0052 reference wallet, refusing to use this device".into());
+            }
+        }
+        Ok(())
+    }
+
+    /// The number of keys checked by every kernel launch
+    pub fn keys_per_launch(&self) -> usize {
+        self.work_size.threads * self.work_size.iterations * self.batch_size
+    }
+
+    /// The current work sizes, see `set_work_size`
+    pub fn work_size(&self) -> Profile {
+        self.work_size
+    }
+
+    pub fn device_name(&self) -> &str {
+        &self.device_name
+    }
+
+    pub fn driver_version(&self) -> &str {
+        &self.driver_version
+    }
+
+    /// Rebuilds the kernels for other work sizes, without recompiling the
+    /// program. Must not be called while launches are in flight.
+    pub fn set_work_size(&mut self, work_size: Profile) -> Result<()> {
+        assert!(self.in_flight.is_empty(), "Launches still in flight");
+        for slot in &mut self.slots {
+            slot.kernel = build_kernel(
+                &self.pro_que,
+                self.mode,
+                &self.pattern,
+                (&slot.counters, &slot.results, &slot.key_root),
+                &work_size,
+            )?;
+            slot.canary_kernel = build_kernel(
+                &self.pro_que,
+                self.mode,
+                &slot.canary,
+                (&slot.counters, &slot.results, &slot.key_root),
+                &work_size,
+            )?;
+        }
+        self.work_size = work_size;
+        Ok(())
+    }
+
+    /// Benchmarks a grid of work sizes and switches to the fastest one, which
+    /// is returned so it can be saved for later runs.
+    pub fn tune(&mut self) -> Result<Profile> {
+        let max_local_work_size = self.pro_que.device().max_wg_size()?;
+        let iteration_counts: &[usize] = match self.mode {
+            SearchMode::Seed | SearchMode::Hd(_) | SearchMode::Multisig(_) => &[1, 4, 16],
+            SearchMode::Incremental
+            | SearchMode::SplitKey(_)
+            | SearchMode::LogicSig(_)
+            | SearchMode::TxId(_) => &[4, 16, 64],
+        };
+        let mut best: Option<(Profile, f64)> = None;
+        for &threads in &[1 << 14, 1 << 16, 1 << 18, 1 << 20, 1 << 22] {
+            for &local_work_size in &[None, Some(32), Some(64), Some(128), Some(256)] {
+                if local_work_size.is_some_and(|size| size > max_local_work_size) {
+                    continue;
+                }
+                for &iterations in iteration_counts {
+                    let profile = Profile {
+                        threads,
+                        local_work_size,
+                        iterations,
+                    };
+                    let keys_per_second = match self
+                        .set_work_size(profile)
+                        .and_then(|()| self.measure())
+                    {
+                        Ok(keys_per_second) => keys_per_second,
+                        Err(err) => {
+                            eprintln!("Skipping {:?}: {}", profile, err);
+                            continue;
+                        }
+                    };
+                    eprintln!(
+                        "{:>8} threads, local work size {:>4}, {:>2} iterations: {:.1} keys/s",
+                        threads,
+                        local_work_size
+                            .map(|size| size.to_string())
+                            .unwrap_or_else(|| "auto".to_string()),
+                        iterations,
+                        keys_per_second,
+                    );
+                    if best.is_none_or(|(_, best_rate)| keys_per_second > best_rate) {
+                        best = Some((profile, keys_per_second));
+                    }
+                }
+            }
+        }
+        let (profile, _) = best.ok_or("No work size could be launched")?;
+        self.set_work_size(profile)?;
+        Ok(profile)
+    }
+
+    /// Keys per second with the current work sizes, over at least a second
+    fn measure(&mut self) -> Result<f64> {
+        // the compressed base point, which is a valid key root in every mode,
+        // an HD chain included, as its chain code and index may be zeros
+        let mut key_root = [0x66u8; 32];
+        key_root[0] = 0x58;
+
+        self.enqueue(&key_root)?;
+        self.finish()?;
+
+        let start_time = Instant::now();
+        let mut launches = 0;
+        let mut keys = 0;
+        while start_time.elapsed() < Duration::from_secs(1) || launches < 2 {
+            while self.can_enqueue() {
+                self.enqueue(&key_root)?;
+            }
+            keys += self.finish()?.keys_checked;
+            launches += 1;
         }
+        while !self.in_flight.is_empty() {
+            keys += self.finish()?.keys_checked;
+        }
+        Ok(keys as f64 / start_time.elapsed().as_secs_f64())
+    }
+
+    /// Whether another launch can be queued with `enqueue`
+    pub fn can_enqueue(&self) -> bool {
+        self.in_flight.len() < self.slots.len()
+    }
 
-        let mut buf = [0u64];
-        self.result.read(&mut buf as &mut [u64]).enq()?;
-        let thread = buf[0];
-        let success = thread != !0u64;
-        if success {
-            self.result.write(&[!0u64] as &[u64]).enq()?;
-            let base = NativeEndian::read_u64(key_root);
-            NativeEndian::write_u64(out, base.wrapping_add(thread));
-            out[8..].copy_from_slice(&key_root[8..]);
+    /// Queues a launch from `key_root` without waiting for it, which is the
+    /// root `canary::pubkey_at_offset` lists for the mode, except that the
+    /// start scalar is passed as its compressed point and the HD account and
+    /// index as their chain. The results are collected by `finish` in the
+    /// order the launches were queued.
+    pub fn enqueue(&mut self, key_root: &[u8]) -> Result<()> {
+        self.enqueue_launch(key_root, None)
+    }
+
+    /// Queues a launch like `enqueue`, which looks for the pattern of
+    /// `matcher` instead of the searched one. Used for canary launches with a
+    /// planted match.
+    pub fn enqueue_canary(&mut self, key_root: &[u8], matcher: &PubkeyMatcher) -> Result<()> {
+        self.enqueue_launch(key_root, Some(matcher))
+    }
+
+    fn enqueue_launch(&mut self, key_root: &[u8], canary: Option<&PubkeyMatcher>) -> Result<()> {
+        assert!(self.can_enqueue(), "All launch slots are in use");
+        let idx = (self.in_flight.back().map(|&i| i + 1).unwrap_or(0)) % self.slots.len();
+        let slot = &mut self.slots[idx];
+        *slot.host_key_root = [0u8; KEY_ROOT_SIZE];
+        slot.host_key_root[..key_root.len()].copy_from_slice(key_root);
+        slot.keys_per_thread = self.work_size.iterations * self.batch_size;
+        slot.kernel_event = Event::empty();
+        slot.transfer_events.clear();
+        slot.done = Event::empty();
+
+        // The in-order queue runs these back to back. The host buffers are not
+        // touched again until `done` completed, which is the last read.
+        unsafe {
+            let mut event = Event::empty();
+            slot.key_root
+                .write(&slot.host_key_root[..])
+                .block(false)
+                .enew(&mut event)
+                .enq()?;
+            slot.transfer_events.push(event);
+            let mut event = Event::empty();
+            slot.counters
+                .write(&ZERO_COUNTERS[..])
+                .block(false)
+                .enew(&mut event)
+                .enq()?;
+            slot.transfer_events.push(event);
+            if let Some(matcher) = canary {
+                let len = matcher.prefix_len().min(32);
+                *slot.host_canary_req = [0u8; 32];
+                *slot.host_canary_mask = [0u8; 32];
+                slot.host_canary_req[..len].copy_from_slice(&matcher.req()[..len]);
+                slot.host_canary_mask[..len].copy_from_slice(&matcher.mask()[..len]);
+                let mut event = Event::empty();
+                slot.canary
+                    .req
+                    .write(&slot.host_canary_req[..])
+                    .block(false)
+                    .enew(&mut event)
+                    .enq()?;
+                slot.transfer_events.push(event);
+                let mut event = Event::empty();
+                slot.canary
+                    .mask
+                    .write(&slot.host_canary_mask[..])
+                    .block(false)
+                    .enew(&mut event)
+                    .enq()?;
+                slot.transfer_events.push(event);
+                slot.canary_kernel.cmd().enew(&mut slot.kernel_event).enq()?;
+            } else {
+                slot.kernel.cmd().enew(&mut slot.kernel_event).enq()?;
+            }
+            let mut event = Event::empty();
+            slot.counters
+                .read(&mut slot.host_counters[..])
+                .block(false)
+                .enew(&mut event)
+                .enq()?;
+            slot.transfer_events.push(event);
+            slot.results
+                .read(&mut slot.host_results[..])
+                .block(false)
+                .enew(&mut slot.done)
+                .enq()?;
         }
-        Ok(success)
+        self.queue.flush()?;
+        self.in_flight.push_back(idx);
+        Ok(())
     }
 
-    pub fn yes(arr: &[u8], print: bool) {
-        if print {
-            print!("\nStarting point -> ... {:?}", arr);
+    /// Waits for the oldest queued launch and returns its results. Gives up
+    /// after the launch timeout, the device should be rebuilt then.
+    pub fn finish(&mut self) -> ::std::result::Result<LaunchResults, LaunchError> {
+        let idx = self
+            .in_flight
+            .pop_front()
+            .expect("No launch in flight");
+        let slot = &self.slots[idx];
+        // poll instead of a blocking wait, which never returns from a hung
+        // kernel
+        let deadline = Instant::now() + self.launch_timeout;
+        while !slot.done.is_complete().map_err(ocl::Error::from)? {
+            if Instant::now() >= deadline {
+                return Err(LaunchError::Timeout(self.launch_timeout));
+            }
+            thread::sleep(Duration::from_micros(500));
+        }
+
+        if let Some(ref mut timings) = self.timings {
+            timings.launches += 1;
+            timings.kernel += event_duration(&slot.kernel_event)?;
+            timings.transfers += event_duration(&slot.done)?;
+            for event in &slot.transfer_events {
+                timings.transfers += event_duration(event)?;
+            }
+        }
+
+        let count = slot.host_counters[0] as usize;
+        if count > MAX_RESULTS {
+            eprintln!(
+                "\nWarning: {} matches in a single launch, only the first {} were kept. Use a lower --gpu-threads or --iterations-per-thread for patterns this easy.",
+                count, MAX_RESULTS
+            );
         }
+        Ok(LaunchResults {
+            offsets: slot.host_results[..count.min(MAX_RESULTS)].to_vec(),
+            keys_checked: slot.host_counters[1] as usize * slot.keys_per_thread,
+        })
+    }
+
+    /// The device time of the launches finished since the last call, if
+    /// profiling is enabled
+    pub fn take_timings(&mut self) -> Option<LaunchTimings> {
+        self.timings.as_mut().map(mem::take)
     }
 
+    /// The seed the seed kernel checked at `offset` from `key_root`
+    pub fn seed_at_offset(key_root: &[u8], offset: u64) -> [u8; 32] {
+        let mut seed = [0u8; 32];
+        seed.copy_from_slice(&key_root[..32]);
+        let base = NativeEndian::read_u64(&key_root[8..]);
+        NativeEndian::write_u64(&mut seed[8..], base.wrapping_add(offset));
+        seed
+    }
+}
+
+// The reads of a launch write into the boxed host buffers of its slot
+// whenever the device gets to them, also after `finish` gave up on it. Those
+// must not be freed before the device is done with them.
+impl Drop for Gpu {
+    fn drop(&mut self) {
+        let deadline = Instant::now() + self.launch_timeout;
+        while self.slots.iter().any(Slot::is_busy) {
+            if Instant::now() >= deadline {
+                eprintln!("The GPU did not finish its queued launches, leaking their buffers");
+                mem::forget(mem::take(&mut self.slots));
+                return;
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
 }
@@ -0,0 +1,458 @@
+use std::fmt;
+use std::num::NonZeroU32;
+use std::sync::Mutex;
+
+use byteorder::{ByteOrder, LittleEndian};
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::Scalar;
+use ring::{hmac, pbkdf2};
+use sha2::{Digest, Sha256, Sha512};
+
+use wordlist;
+
+/// Indices from here on are hardened, derived from the private key
+pub const HARDENED: u32 = 0x8000_0000;
+
+/// ARC-0052 paths are m/44'/283'/account'/0/index
+const PURPOSE: u32 = 44;
+const COIN_TYPE: u32 = 283;
+const CHANGE: u32 = 0;
+
+/// The size of the chain a GPU launch derives keys from: the compressed
+/// public key and chain code of m/44'/283'/account'/0, the index of the first
+/// key as 4 little endian bytes and the number of truncated bits.
+pub const DEVICE_ROOT_SIZE: usize = 32 + 32 + 4 + 1;
+
+/// The wallet the tests of the ARC-0052 reference implementation use
+pub const REFERENCE_MNEMONIC: &str = "salon zoo engage submit smile frost later decide wing sight chaos renew lizard rely canal coral scene hobby scare step bus leaf tobacco slice";
+
+/// The public keys of m/44'/283'/0'/0/0 to 2 of `REFERENCE_MNEMONIC`. The
+/// Peikert ones are the known answers of the reference implementation, the
+/// Khovratovich ones come from an independent implementation of the paper.
+pub fn reference_pubkeys(derivation: Derivation) -> [&'static str; 3] {
+    match derivation {
+        Derivation::Khovratovich => [
+            "62fe832b7ad10544be8337a670435e5064ae4a66e77bd78909765b46b576a6f3",
+            "530461002eaccec0c7b5795925aa104a7fb45f85ef0aa95bbb5be93b6f8537ad",
+            "2281c81bee04ee039fa482c283541c6ab06c8324db6f1cc59c68252e1d58bcb3",
+        ],
+        Derivation::Peikert => [
+            "7bda7ac12627b2c259f1df6875d30c10b35f55b33ad2cc8ea2736eaa3ebcfab9",
+            "5bae8828f111064637ac5061bd63bc4fcfe4a833252305f25eeab9c64ecdf519",
+            "00a72635e97cba966529e9bfb4baf4a32d7b8cd2fcd8e2476ce5be1177848cb3",
+        ],
+    }
+}
+
+/// How many bits of the left half of the HMAC output are dropped before it
+/// is added to the parent key
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Derivation {
+    /// The original BIP32-Ed25519 paper, keeping 224 bits
+    Khovratovich,
+    /// Peikert's amendment keeping 247 bits, which ARC-0052 wallets use by
+    /// default
+    Peikert,
+}
+
+impl Derivation {
+    pub fn from_name(name: &str) -> Option<Derivation> {
+        match name {
+            "khovratovich" => Some(Derivation::Khovratovich),
+            "peikert" => Some(Derivation::Peikert),
+            _ => None,
+        }
+    }
+
+    fn truncated_bits(self) -> u8 {
+        match self {
+            Derivation::Khovratovich => 32,
+            Derivation::Peikert => 9,
+        }
+    }
+}
+
+/// The secret root of an HD wallet: kL, kR and the chain code
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Root {
+    key: [u8; 96],
+    derivation: Derivation,
+}
+
+// never print the secret
+impl fmt::Debug for Root {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Root({:?})", self.derivation)
+    }
+}
+
+/// The public part of a node, which non-hardened children can be derived
+/// from without the secret key
+pub struct Chain {
+    point: EdwardsPoint,
+    pubkey: [u8; 32],
+    chain_code: [u8; 32],
+    derivation: Derivation,
+}
+
+fn hmac_sha512(key: &[u8], parts: &[&[u8]]) -> [u8; 64] {
+    let mut ctx = hmac::Context::with_key(&hmac::Key::new(hmac::HMAC_SHA512, key));
+    for part in parts {
+        ctx.update(part);
+    }
+    let mut out = [0u8; 64];
+    out.copy_from_slice(ctx.sign().as_ref());
+    out
+}
+
+/// kL * B without clamping
+fn scalar_pubkey(kl: &[u8]) -> [u8; 32] {
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(&kl[..32]);
+    EdwardsPoint::mul_base(&Scalar::from_bytes_mod_order(scalar))
+        .compress()
+        .to_bytes()
+}
+
+/// 8 * trunc(zL), the amount a child's kL differs from its parent's. The top
+/// `truncated_bits` bits are dropped, so the product never overflows.
+fn child_offset(z: &[u8; 64], derivation: Derivation) -> [u8; 32] {
+    let mut zl = [0u8; 32];
+    zl.copy_from_slice(&z[..32]);
+    let mut remaining = derivation.truncated_bits();
+    for byte in zl.iter_mut().rev() {
+        if remaining >= 8 {
+            *byte = 0;
+            remaining -= 8;
+        } else {
+            *byte &= 0xff >> remaining;
+            break;
+        }
+    }
+    let mut offset = [0u8; 32];
+    let mut carry = 0u8;
+    for (out, byte) in offset.iter_mut().zip(zl.iter()) {
+        *out = (byte << 3) | carry;
+        carry = byte >> 5;
+    }
+    offset
+}
+
+/// a + b modulo 2^256, little endian
+fn add_le(a: &[u8], b: &[u8]) -> [u8; 32] {
+    let mut sum = [0u8; 32];
+    let mut carry = 0u16;
+    for i in 0..32 {
+        carry += a[i] as u16 + b[i] as u16;
+        sum[i] = carry as u8;
+        carry >>= 8;
+    }
+    sum
+}
+
+fn derive_child(node: &[u8; 96], index: u32, derivation: Derivation) -> [u8; 96] {
+    let (kl, kr, chain_code) = (&node[..32], &node[32..64], &node[64..]);
+    let mut index_bytes = [0u8; 4];
+    LittleEndian::write_u32(&mut index_bytes, index);
+    let (z, child_chain_code) = if index >= HARDENED {
+        (
+            hmac_sha512(chain_code, &[&[0x00], kl, kr, &index_bytes]),
+            hmac_sha512(chain_code, &[&[0x01], kl, kr, &index_bytes]),
+        )
+    } else {
+        let pubkey = scalar_pubkey(kl);
+        (
+            hmac_sha512(chain_code, &[&[0x02], &pubkey, &index_bytes]),
+            hmac_sha512(chain_code, &[&[0x03], &pubkey, &index_bytes]),
+        )
+    };
+    let mut child = [0u8; 96];
+    child[..32].copy_from_slice(&add_le(kl, &child_offset(&z, derivation)));
+    child[32..64].copy_from_slice(&add_le(kr, &z[32..]));
+    child[64..].copy_from_slice(&child_chain_code[32..]);
+    child
+}
+
+/// The 64 byte seed of a BIP39 mnemonic. The checksum must be valid.
+pub fn bip39_seed(mnemonic: &str, passphrase: &str) -> Result<[u8; 64], String> {
+    let words: Vec<&str> = mnemonic.split_whitespace().collect();
+    if words.len() < 12 || words.len() > 24 || !words.len().is_multiple_of(3) {
+        return Err(format!(
+            "A BIP39 mnemonic has 12, 15, 18, 21 or 24 words, not {}",
+            words.len()
+        ));
+    }
+    let mut bytes = vec![0u8; (words.len() * 11).div_ceil(8)];
+    for (i, word) in words.iter().enumerate() {
+        let index = wordlist::index_of(word)
+            .ok_or_else(|| format!("Unknown mnemonic word: {}", word))?;
+        for bit in 0..11 {
+            if (index >> (10 - bit)) & 1 == 1 {
+                let pos = i * 11 + bit;
+                bytes[pos / 8] |= 0x80 >> (pos % 8);
+            }
+        }
+    }
+    let checksum_bits = words.len() * 11 / 33;
+    let entropy_len = checksum_bits * 4;
+    let hash = Sha256::digest(&bytes[..entropy_len]);
+    if bytes[entropy_len] >> (8 - checksum_bits) != hash[0] >> (8 - checksum_bits) {
+        return Err("Invalid mnemonic checksum".to_string());
+    }
+
+    let mut seed = [0u8; 64];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA512,
+        NonZeroU32::new(2048).unwrap(),
+        format!("mnemonic{}", passphrase).as_bytes(),
+        words.join(" ").as_bytes(),
+        &mut seed,
+    );
+    Ok(seed)
+}
+
+/// The derivation path of a key
+pub fn path(account: u32, index: u32) -> String {
+    format!("m/{}'/{}'/{}'/{}/{}", PURPOSE, COIN_TYPE, account, CHANGE, index)
+}
+
+/// The key root of an HD launch, which holds the account and the index of
+/// the first key it checks. The device gets `Root::device_root` instead.
+pub fn launch_root(account: u32, first_index: u32) -> [u8; 32] {
+    let mut root = [0u8; 32];
+    LittleEndian::write_u32(&mut root[..4], account);
+    LittleEndian::write_u32(&mut root[4..8], first_index);
+    root
+}
+
+/// The account and index of the first key of `launch_root`
+pub fn parse_launch_root(root: &[u8; 32]) -> (u32, u32) {
+    (
+        LittleEndian::read_u32(&root[..4]),
+        LittleEndian::read_u32(&root[4..8]),
+    )
+}
+
+impl Root {
+    /// The root key of a BIP39 seed, as derived by ARC-0052 wallets
+    pub fn from_seed(seed: &[u8], derivation: Derivation) -> Root {
+        let mut k = [0u8; 64];
+        k.copy_from_slice(&Sha512::digest(seed));
+        // retry until the third highest bit of kL is clear
+        while k[31] & 0b0010_0000 != 0 {
+            k = hmac_sha512(&k[..32], &[&k[32..]]);
+        }
+        k[0] &= 0b1111_1000;
+        k[31] &= 0b0111_1111;
+        k[31] |= 0b0100_0000;
+
+        let mut key = [0u8; 96];
+        key[..64].copy_from_slice(&k);
+        key[64..].copy_from_slice(&Sha256::new().chain_update([0x01]).chain_update(seed).finalize());
+        Root { key, derivation }
+    }
+
+    pub fn from_mnemonic(mnemonic: &str, passphrase: &str, derivation: Derivation) -> Result<Root, String> {
+        Ok(Root::from_seed(&bip39_seed(mnemonic, passphrase)?, derivation))
+    }
+
+    /// A 96 byte root key in hex: kL, kR and the chain code
+    pub fn from_hex(hex_key: &str, derivation: Derivation) -> Result<Root, String> {
+        let bytes = hex::decode(hex_key.trim()).map_err(|err| format!("Invalid root key: {}", err))?;
+        if bytes.len() != 96 {
+            return Err(format!("A root key has 96 bytes, not {}", bytes.len()));
+        }
+        if bytes[0] & 0b111 != 0 || bytes[31] & 0b1000_0000 != 0 || bytes[31] & 0b0100_0000 == 0 {
+            return Err("Invalid root key: kL is not clamped".to_string());
+        }
+        let mut key = [0u8; 96];
+        key.copy_from_slice(&bytes);
+        Ok(Root { key, derivation })
+    }
+
+    pub fn derivation(&self) -> Derivation {
+        self.derivation
+    }
+
+    /// The node m/44'/283'/account'/0
+    fn change_node(&self, account: u32) -> [u8; 96] {
+        [PURPOSE | HARDENED, COIN_TYPE | HARDENED, account | HARDENED, CHANGE]
+            .iter()
+            .fold(self.key, |node, &index| derive_child(&node, index, self.derivation))
+    }
+
+    /// The chain the keys of an account are derived from
+    pub fn account_chain(&self, account: u32) -> Chain {
+        let node = self.change_node(account);
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&node[64..]);
+        Chain::new(scalar_pubkey(&node[..32]), chain_code, self.derivation)
+            .expect("Derived an invalid public key")
+    }
+
+    /// The extended secret key of m/44'/283'/account'/0/index: the scalar
+    /// kL followed by kR, which seeds signature nonces
+    pub fn child_key(&self, account: u32, index: u32) -> [u8; 64] {
+        let node = derive_child(&self.change_node(account), index, self.derivation);
+        let mut key = [0u8; 64];
+        key.copy_from_slice(&node[..64]);
+        key
+    }
+
+    pub fn device_root(&self, account: u32, first_index: u32) -> [u8; DEVICE_ROOT_SIZE] {
+        self.account_chain(account).device_root(first_index)
+    }
+}
+
+impl Chain {
+    /// `None` if `pubkey` is not a valid point
+    pub fn new(pubkey: [u8; 32], chain_code: [u8; 32], derivation: Derivation) -> Option<Chain> {
+        Some(Chain {
+            point: CompressedEdwardsY(pubkey).decompress()?,
+            pubkey,
+            chain_code,
+            derivation,
+        })
+    }
+
+    /// The public keys of `count` consecutive non-hardened children, each
+    /// the parent's public key plus [8 * trunc(zL)]B
+    pub fn child_pubkeys(&self, first_index: u32, count: usize) -> Vec<[u8; 32]> {
+        let mut index_bytes = [0u8; 4];
+        let points: Vec<EdwardsPoint> = (0..count)
+            .map(|i| {
+                LittleEndian::write_u32(&mut index_bytes, first_index + i as u32);
+                let z = hmac_sha512(&self.chain_code, &[&[0x02], &self.pubkey, &index_bytes]);
+                let offset = Scalar::from_bytes_mod_order(child_offset(&z, self.derivation));
+                self.point + EdwardsPoint::mul_base(&offset)
+            })
+            .collect();
+        EdwardsPoint::compress_batch_alloc(&points)
+            .into_iter()
+            .map(|point| point.to_bytes())
+            .collect()
+    }
+
+    pub fn child_pubkey(&self, index: u32) -> [u8; 32] {
+        self.child_pubkeys(index, 1)[0]
+    }
+
+    /// What the HD kernel derives the keys from `first_index` on from, see
+    /// `DEVICE_ROOT_SIZE`
+    pub fn device_root(&self, first_index: u32) -> [u8; DEVICE_ROOT_SIZE] {
+        let mut root = [0u8; DEVICE_ROOT_SIZE];
+        root[..32].copy_from_slice(&self.pubkey);
+        root[32..64].copy_from_slice(&self.chain_code);
+        LittleEndian::write_u32(&mut root[64..68], first_index);
+        root[68] = self.derivation.truncated_bits();
+        root
+    }
+}
+
+/// Hands out disjoint ranges of child indices to the devices searching an HD
+/// wallet, starting at index 0 of the first account
+pub struct Cursor {
+    next: Mutex<(u32, u32)>,
+}
+
+impl Cursor {
+    pub fn new(account: u32) -> Cursor {
+        Cursor {
+            next: Mutex::new((account, 0)),
+        }
+    }
+
+    /// Reserves `count` consecutive indices of one account and returns the
+    /// account and the first index. Moves on to the next account when the
+    /// current one has fewer non-hardened indices left.
+    pub fn reserve(&self, count: usize) -> (u32, u32) {
+        assert!(count <= HARDENED as usize, "Too many keys for one account");
+        let mut next = self.next.lock().unwrap();
+        if next.1 as usize + count > HARDENED as usize {
+            next.0 += 1;
+            next.1 = 0;
+            assert!(next.0 < HARDENED, "Searched every account of the wallet");
+        }
+        let reserved = *next;
+        next.1 += count as u32;
+        reserved
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use derivation;
+
+    #[test]
+    fn test_bip39_seed() {
+        let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        assert_eq!(
+            hex::encode(&bip39_seed(mnemonic, "TREZOR").unwrap()[..]),
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"
+        );
+        assert!(bip39_seed(&"abandon ".repeat(12), "").is_err());
+        assert!(bip39_seed("abandon about", "").is_err());
+    }
+
+    #[test]
+    fn test_public_derivation() {
+        for &derivation in &[Derivation::Khovratovich, Derivation::Peikert] {
+            let root = Root::from_seed(&[0x2a; 64], derivation);
+            let chain = root.account_chain(1);
+            let pubkeys = chain.child_pubkeys(HARDENED - 2, 2);
+            for (i, pubkey) in pubkeys.iter().enumerate() {
+                let key = root.child_key(1, HARDENED - 2 + i as u32);
+                assert_eq!(*pubkey, scalar_pubkey(&key[..32]));
+            }
+            assert_ne!(chain.child_pubkey(0), root.account_chain(0).child_pubkey(0));
+        }
+        let khovratovich = Root::from_seed(&[0x2a; 64], Derivation::Khovratovich);
+        let peikert = Root::from_seed(&[0x2a; 64], Derivation::Peikert);
+        assert_ne!(khovratovich.child_key(0, 0), peikert.child_key(0, 0));
+        assert_eq!(path(0, 7), "m/44'/283'/0'/0/7");
+    }
+
+    #[test]
+    fn test_reference_wallet() {
+        let root = Root::from_mnemonic(REFERENCE_MNEMONIC, "", Derivation::Peikert).unwrap();
+        assert_eq!(
+            hex::encode(&root.key[..]),
+            "a8ba80028922d9fcfa055c78aede55b5c575bcd8d5a53168edf45f36d9ec8f4694592b4bc892907583e22669ecdf1b0409a9f3bd5549f2dd751b51360909cd05796b9206ec30e142e94b790a98805bf999042b55046963174ee6cee2d0375946"
+        );
+        for &derivation in &[Derivation::Khovratovich, Derivation::Peikert] {
+            let root = Root::from_mnemonic(REFERENCE_MNEMONIC, "", derivation).unwrap();
+            let chain = root.account_chain(0);
+            for (index, pubkey) in reference_pubkeys(derivation).iter().enumerate() {
+                assert_eq!(hex::encode(chain.child_pubkey(index as u32)), *pubkey);
+                let key = root.child_key(0, index as u32);
+                assert_eq!(hex::encode(scalar_pubkey(&key[..32])), *pubkey);
+            }
+        }
+
+        // down to the address, in other accounts too
+        let addresses = [
+            (Derivation::Peikert, 0, 0, "PPNHVQJGE6ZMEWPR35UHLUYMCCZV6VNTHLJMZDVCONXKUPV47K4WGQJWGI"),
+            (Derivation::Peikert, 1, 0, "GWGYYQ4CTEUETJ3EIOHAFMOELQWKJ2DLXT7BB7K3SY7TMEABFPEZRZUOB4"),
+            (Derivation::Peikert, 2, 1, "D4HXL654UEVSEURZOMMRAYNS7FSSE5AOCONDIIGHGBYXVRNQ37ANGUX6EU"),
+            (Derivation::Khovratovich, 1, 0, "TYJGIP3MABUNZ5J3ATNM5VXYYGUQVUQ4SVFGNX2BIDLZGAYWNJT4EPIZYQ"),
+            (Derivation::Khovratovich, 2, 1, "RJO56YWVDIWFBZI5XLKGGQ2WZRZDCSUB5XMRPLER3KLEO6U7WWYJKKDACY"),
+        ];
+        for &(hd_derivation, account, index, address) in &addresses {
+            let root = Root::from_mnemonic(REFERENCE_MNEMONIC, "", hd_derivation).unwrap();
+            let pubkey = root.account_chain(account).child_pubkey(index);
+            assert_eq!(derivation::pubkey_to_address(&pubkey), address);
+        }
+    }
+
+    #[test]
+    fn test_cursor() {
+        let cursor = Cursor::new(3);
+        assert_eq!(cursor.reserve(10), (3, 0));
+        assert_eq!(cursor.reserve(HARDENED as usize - 20), (3, 10));
+        assert_eq!(cursor.reserve(10), (3, HARDENED - 10));
+        assert_eq!(cursor.reserve(1), (4, 0));
+        let root = launch_root(4, 1);
+        assert_eq!(parse_launch_root(&root), (4, 1));
+    }
+}
@@ -0,0 +1,160 @@
+use byteorder::{ByteOrder, LittleEndian};
+use sha2::{Digest, Sha512_256};
+
+/// The domain separation prefix of program hashes
+const PROGRAM_PREFIX: &[u8] = b"Program";
+
+/// The largest logic signature the protocol accepts
+const MAX_PROGRAM_SIZE: usize = 1000;
+
+/// The nonce needs room for the 8 byte counter that is added to it
+const MIN_NONCE_SIZE: usize = 8;
+const MAX_NONCE_SIZE: usize = 32;
+
+/// A compiled program with a placeholder constant, which the search replaces
+/// with nonces until the contract account address matches
+#[derive(PartialEq, Eq, Debug)]
+pub struct Template {
+    /// "Program" followed by the program, which is what the address hashes
+    message: Vec<u8>,
+    /// Where the placeholder starts in `message`
+    nonce_pos: usize,
+    nonce_len: usize,
+}
+
+impl Template {
+    /// `placeholder` must occur exactly once in `program`, e.g. the bytes of
+    /// a `pushbytes` constant that the program otherwise ignores
+    pub fn new(program: &[u8], placeholder: &[u8]) -> Result<Template, String> {
+        if program.len() > MAX_PROGRAM_SIZE {
+            return Err(format!(
+                "Logic signatures have at most {} bytes, this one has {}",
+                MAX_PROGRAM_SIZE,
+                program.len()
+            ));
+        }
+        if placeholder.len() < MIN_NONCE_SIZE || placeholder.len() > MAX_NONCE_SIZE {
+            return Err(format!(
+                "The placeholder must have {} to {} bytes, not {}",
+                MIN_NONCE_SIZE,
+                MAX_NONCE_SIZE,
+                placeholder.len()
+            ));
+        }
+        let positions: Vec<usize> = program
+            .windows(placeholder.len())
+            .enumerate()
+            .filter(|&(_, window)| window == placeholder)
+            .map(|(pos, _)| pos)
+            .collect();
+        let pos = match positions[..] {
+            [pos] => pos,
+            [] => return Err("The placeholder does not occur in the program".to_string()),
+            _ => {
+                return Err(format!(
+                    "The placeholder occurs {} times in the program, it must be unique",
+                    positions.len()
+                ))
+            }
+        };
+        let mut message = PROGRAM_PREFIX.to_vec();
+        message.extend_from_slice(program);
+        Ok(Template {
+            message,
+            nonce_pos: PROGRAM_PREFIX.len() + pos,
+            nonce_len: placeholder.len(),
+        })
+    }
+
+    /// "Program" followed by the program with the placeholder
+    pub fn message(&self) -> &[u8] {
+        &self.message
+    }
+
+    pub fn nonce_pos(&self) -> usize {
+        self.nonce_pos
+    }
+
+    pub fn nonce_len(&self) -> usize {
+        self.nonce_len
+    }
+
+    /// The nonce a launch checks at `offset` from `root`
+    pub fn nonce_at_offset(&self, root: &[u8; 32], offset: u64) -> Vec<u8> {
+        nonce_at_offset(root, self.nonce_len, offset)
+    }
+
+    /// The program with the placeholder replaced by `nonce`
+    pub fn program(&self, nonce: &[u8]) -> Vec<u8> {
+        let mut program = self.message[PROGRAM_PREFIX.len()..].to_vec();
+        let pos = self.nonce_pos - PROGRAM_PREFIX.len();
+        program[pos..pos + self.nonce_len].copy_from_slice(nonce);
+        program
+    }
+
+    /// The contract account address of the program with `nonce`, without
+    /// the checksum
+    pub fn address(&self, nonce: &[u8]) -> [u8; 32] {
+        let mut address = [0u8; 32];
+        address.copy_from_slice(
+            &Sha512_256::new()
+                .chain_update(&self.message[..self.nonce_pos])
+                .chain_update(nonce)
+                .chain_update(&self.message[self.nonce_pos + self.nonce_len..])
+                .finalize(),
+        );
+        address
+    }
+}
+
+/// The nonce of `len` bytes a launch checks at `offset` from `root`: the
+/// first bytes of the root, with the offset added to the first 8 as a little
+/// endian number. The kernels vary transaction notes the same way.
+pub fn nonce_at_offset(root: &[u8; 32], len: usize, offset: u64) -> Vec<u8> {
+    let mut nonce = root[..len].to_vec();
+    let base = LittleEndian::read_u64(&nonce);
+    LittleEndian::write_u64(&mut nonce, base.wrapping_add(offset));
+    nonce
+}
+
+/// The contract account address of a program
+pub fn program_address(program: &[u8]) -> [u8; 32] {
+    let mut address = [0u8; 32];
+    address.copy_from_slice(
+        &Sha512_256::new()
+            .chain_update(PROGRAM_PREFIX)
+            .chain_update(program)
+            .finalize(),
+    );
+    address
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // #pragma version 8; pushbytes 0xa1a1a1a1a1a1a1a1; pop; pushint 1
+    const PROGRAM: [u8; 14] = [
+        0x08, 0x80, 0x08, 0xa1, 0xa1, 0xa1, 0xa1, 0xa1, 0xa1, 0xa1, 0xa1, 0x48, 0x81, 0x01,
+    ];
+
+    #[test]
+    fn test_template() {
+        let template = Template::new(&PROGRAM, &[0xa1; 8]).unwrap();
+        let mut root = [0u8; 32];
+        root[..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let nonce = template.nonce_at_offset(&root, 2);
+        assert_eq!(nonce, [1, 0, 0, 0, 0, 0, 0, 0]);
+        let program = template.program(&nonce);
+        assert_eq!(program[..3], PROGRAM[..3]);
+        assert_eq!(program[3..11], nonce[..]);
+        assert_eq!(program[11..], PROGRAM[11..]);
+        assert_eq!(template.address(&nonce), program_address(&program));
+
+        assert!(Template::new(&PROGRAM, &[0xa1; 4]).is_err());
+        assert!(Template::new(&PROGRAM, &[0xb2; 8]).is_err());
+        let mut twice = PROGRAM.to_vec();
+        twice.extend_from_slice(&PROGRAM);
+        assert!(Template::new(&twice, &[0xa1; 8]).is_err());
+    }
+}
@@ -1,19 +1,28 @@
+use std::collections::VecDeque;
 use std::f64;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
 use std::process;
 use std::sync::atomic;
 use std::sync::atomic::AtomicUsize;
+use std::sync::mpsc;
 use std::sync::Arc;
 use std::thread;
+use std::hint;
 use std::time::{Duration, Instant};
 
 extern crate clap;
+extern crate data_encoding;
 extern crate digest;
 extern crate hex;
 extern crate num_cpus;
-extern crate algonaut;
 extern crate ring;
+extern crate serde_json;
 extern crate sha2;
 extern crate byteorder;
+extern crate curve25519_dalek;
+use byteorder::{ByteOrder, LittleEndian};
 
 extern crate rand;
 use rand::rngs::OsRng;
@@ -27,25 +36,91 @@ use num_traits::ToPrimitive;
 #[cfg(feature = "gpu")]
 extern crate ocl;
 
-use algonaut::transaction::account::Account;
+#[cfg(feature = "wgpu")]
+extern crate pollster;
+#[cfg(feature = "wgpu")]
+extern crate wgpu;
+
+
+mod cache;
+
+mod canary;
+use canary::Canary;
 
 mod derivation;
 use derivation::ADDRESS_ALPHABET;
 
+mod hd;
+
+mod logicsig;
+
+mod mnemonic;
+
+mod multisig;
+
+mod proof;
+
 mod pubkey_matcher;
 use pubkey_matcher::PubkeyMatcher;
 
+mod split_key;
+
+mod sweep;
+use sweep::Sweep;
+
+mod throttle;
+use throttle::Throttle;
+
+mod txid;
+
+mod wordlist;
+
 #[cfg(feature = "gpu")]
 mod gpu_impl;
 
+#[cfg(feature = "gpu")]
+mod opencl_features;
+
+#[cfg(feature = "wgpu")]
+mod wgpu_impl;
+
 mod gpu;
-use gpu::{Gpu, GpuOptions};
+use gpu::{Gpu, GpuOptions, LaunchError, LaunchTimings, SearchMode};
+#[cfg(feature = "wgpu")]
+use gpu::WgpuGpu;
+
+/// A key found by a device, which is checked by a verifier thread
+enum Candidate {
+    Seed([u8; 32]),
+    ExpandedKey([u8; 64]),
+    HdKey { root: hd::Root, account: u32, index: u32 },
+    LogicSig { template: &'static logicsig::Template, nonce: Vec<u8> },
+    MultisigMember { template: &'static multisig::Template, seed: [u8; 32] },
+    SplitKeyOffset { pubkey: [u8; 32], offset: [u8; 32] },
+    TxId { template: &'static txid::Template, nonce: Vec<u8> },
+}
+
+/// The number of seeds a CPU thread derives per call to the batched derivation
+const CPU_BATCH_SIZE: usize = 4096;
 
 struct ThreadParams {
     limit: usize,
     found_n: Arc<AtomicUsize>,
     attempts: Arc<AtomicUsize>,
     matcher: Arc<PubkeyMatcher>,
+    /// The message of `--sign-challenge`, signed with every key found
+    challenge: Option<&'static [u8]>,
+}
+
+/// Prints the proof that we hold the key of a found address, if one is asked
+/// for
+fn print_challenge_signature(params: &ThreadParams, sign: impl FnOnce(&[u8]) -> [u8; 64]) {
+    if let Some(challenge) = params.challenge {
+        println!(
+            "Challenge Signature (base64): {} ",
+            data_encoding::BASE64.encode(&sign(challenge)),
+        );
+    }
 }
 
 
@@ -56,24 +131,260 @@ fn check_solution(params: &ThreadParams, key_material: [u8; 32]) -> bool {
 
     if matches {
 
-        let wallet = Account::from_seed(key_material);
         println!(
             "\nFound matching account!\nPrivate Key: {:?} \nAddress: {} \nMnemonic: {}",
-            wallet.seed(),
-            wallet.address(),
-            wallet.mnemonic()
+            key_material,
+            derivation::pubkey_to_address(&public_key),
+            mnemonic::from_seed(&key_material)
         );
+        print_challenge_signature(params, |challenge| proof::sign_seed(&key_material, challenge));
         println!();
+        count_solution(params);
+    }
+    matches
+}
 
-        if params.limit != 0
-            && params.found_n.fetch_add(1, atomic::Ordering::Relaxed) + 1 >= params.limit
+fn check_expanded_solution(params: &ThreadParams, expanded_key: [u8; 64]) -> bool {
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(&expanded_key[..32]);
+    let public_key = derivation::ed25519_scalar_to_pubkey(&scalar);
+    let matches = params.matcher.matches(&public_key);
+
+    if matches {
+        // make sure the exported key can actually sign for the address
+        let signature = derivation::ed25519_expanded_sign(&expanded_key, b"algomania-gpu");
+        if ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, &public_key[..])
+            .verify(b"algomania-gpu", &signature)
+            .is_err()
+        {
+            eprintln!("Warning: the expanded key below failed to sign a test message");
+        }
+        println!(
+            "\nFound matching account!\nExpanded Secret Key: {} \nAddress: {} \nThis key has no seed or mnemonic, it can only be used with wallets and libraries that accept expanded Ed25519 keys",
+            hex::encode(&expanded_key[..]),
+            derivation::pubkey_to_address(&public_key),
+        );
+        print_challenge_signature(params, |challenge| proof::sign_expanded(&expanded_key, challenge));
+        println!();
+        count_solution(params);
+    }
+    matches
+}
+
+fn check_hd_solution(params: &ThreadParams, root: &hd::Root, account: u32, index: u32) -> bool {
+    let key = root.child_key(account, index);
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(&key[..32]);
+    let public_key = derivation::ed25519_scalar_to_pubkey(&scalar);
+    let matches = params.matcher.matches(&public_key);
+
+    if matches {
+        // make sure the wallet can actually sign for the address
+        let signature = derivation::ed25519_expanded_sign(&key, b"algomania-gpu");
+        if ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, &public_key[..])
+            .verify(b"algomania-gpu", &signature)
+            .is_err()
         {
-            process::exit(0);
+            eprintln!("Warning: the derived key below failed to sign a test message");
+        }
+        println!(
+            "\nFound matching account!\nDerivation Path: {} \nAddress: {} \nAdd the account with this derivation path to your HD wallet to use it",
+            hd::path(account, index),
+            derivation::pubkey_to_address(&public_key),
+        );
+        print_challenge_signature(params, |challenge| proof::sign_expanded(&key, challenge));
+        println!();
+        count_solution(params);
+    }
+    matches
+}
+
+fn check_logicsig_solution(params: &ThreadParams, template: &logicsig::Template, nonce: &[u8]) -> bool {
+    let program = template.program(nonce);
+    let address = logicsig::program_address(&program);
+    let matches = params.matcher.matches(&address);
+
+    if matches {
+        println!(
+            "\nFound matching contract account!\nAddress: {} \nNonce: {} \nProgram (base64): {} \nSign transactions of this account with the logic signature of the program above",
+            derivation::pubkey_to_address(&address),
+            hex::encode(nonce),
+            data_encoding::BASE64.encode(&program),
+        );
+        println!();
+        count_solution(params);
+    }
+    matches
+}
+
+fn check_multisig_solution(params: &ThreadParams, template: &multisig::Template, seed: [u8; 32]) -> bool {
+    let public_key = derivation::ed25519_privkey_to_pubkey(&seed);
+    let address = template.address(&public_key);
+    let matches = params.matcher.matches(&address);
+
+    if matches {
+        let members: Vec<String> = template
+            .members(&public_key)
+            .into_iter()
+            .map(|member| derivation::pubkey_to_address(&member))
+            .collect();
+        println!(
+            "\nFound matching multisig account!\nMultisig Address: {} \nVersion: {} \nThreshold: {} \nMembers: {} \nPreimage: {} \nNew Member Private Key: {:?} \nNew Member Address: {} \nNew Member Mnemonic: {}",
+            derivation::pubkey_to_address(&address),
+            multisig::VERSION,
+            template.threshold(),
+            members.join(","),
+            hex::encode(template.preimage(&public_key)),
+            seed,
+            derivation::pubkey_to_address(&public_key),
+            mnemonic::from_seed(&seed)
+        );
+        // only the new member's key is ours, so it signs as the member
+        print_challenge_signature(params, |challenge| proof::sign_seed(&seed, challenge));
+        println!();
+        count_solution(params);
+    }
+    matches
+}
+
+fn check_split_key_solution(params: &ThreadParams, pubkey: &[u8; 32], offset: [u8; 32]) -> bool {
+    let point = split_key::parse_point(pubkey).expect("Invalid split-key request");
+    let public_key = split_key::offset_pubkey(&point, &offset);
+    let matches = params.matcher.matches(&public_key);
+
+    if matches {
+        println!(
+            "\nFound matching split-key account!\nAddress: {} \nKey Offset: {} \nSend the offset to the requester, who gets the secret key with: split-key-combine --secret-file FILE --offset {}",
+            derivation::pubkey_to_address(&public_key),
+            hex::encode(offset),
+            hex::encode(offset),
+        );
+        println!();
+        count_solution(params);
+    }
+    matches
+}
+
+fn check_txid_solution(params: &ThreadParams, template: &txid::Template, nonce: &[u8]) -> bool {
+    let id = template.id(nonce);
+    let matches = params.matcher.matches(&id);
+
+    if matches {
+        if template.is_group() {
+            println!(
+                "\nFound matching group ID!\nGroup ID: {} \nGroup ID (base64): {} ",
+                data_encoding::BASE32_NOPAD.encode(&id),
+                data_encoding::BASE64.encode(&id),
+            );
+        } else {
+            println!(
+                "\nFound matching transaction ID!\nTransaction ID: {} ",
+                data_encoding::BASE32_NOPAD.encode(&id),
+            );
         }
+        println!(
+            "Note Nonce: {} \nTransactions (base64): {} \nDecode the transactions into a file and sign them with goal clerk sign, the signatures don't change the ID",
+            hex::encode(nonce),
+            data_encoding::BASE64.encode(&template.transactions(nonce)),
+        );
+        println!();
+        count_solution(params);
     }
     matches
 }
 
+/// Checks and prints the keys found by a device off its own thread, so the
+/// device thread can go straight back to queueing launches. The thread ends
+/// once the sender is dropped and every candidate was checked.
+fn start_verifier_thread(
+    params: ThreadParams,
+    device: &'static str,
+) -> (mpsc::Sender<Candidate>, thread::JoinHandle<()>) {
+    let (sender, receiver) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        for candidate in receiver {
+            match candidate {
+                Candidate::Seed(seed) => {
+                    if !check_solution(&params, seed) {
+                        eprintln!(
+                            "{} returned non-matching solution: {}",
+                            device,
+                            hex::encode_upper(seed),
+                        );
+                    }
+                }
+                Candidate::ExpandedKey(expanded_key) => {
+                    if !check_expanded_solution(&params, expanded_key) {
+                        eprintln!(
+                            "{} returned non-matching expanded key: {}",
+                            device,
+                            hex::encode_upper(&expanded_key[..]),
+                        );
+                    }
+                }
+                Candidate::HdKey {
+                    root,
+                    account,
+                    index,
+                } => {
+                    if !check_hd_solution(&params, &root, account, index) {
+                        eprintln!(
+                            "{} returned non-matching HD key: {}",
+                            device,
+                            hd::path(account, index),
+                        );
+                    }
+                }
+                Candidate::MultisigMember { template, seed } => {
+                    if !check_multisig_solution(&params, template, seed) {
+                        eprintln!(
+                            "{} returned non-matching multisig member: {}",
+                            device,
+                            hex::encode_upper(seed),
+                        );
+                    }
+                }
+                Candidate::SplitKeyOffset { pubkey, offset } => {
+                    if !check_split_key_solution(&params, &pubkey, offset) {
+                        eprintln!(
+                            "{} returned non-matching split-key offset: {}",
+                            device,
+                            hex::encode_upper(offset),
+                        );
+                    }
+                }
+                Candidate::LogicSig { template, nonce } => {
+                    if !check_logicsig_solution(&params, template, &nonce) {
+                        eprintln!(
+                            "{} returned non-matching logic signature nonce: {}",
+                            device,
+                            hex::encode_upper(&nonce),
+                        );
+                    }
+                }
+                Candidate::TxId { template, nonce } => {
+                    if !check_txid_solution(&params, template, &nonce) {
+                        eprintln!(
+                            "{} returned non-matching note nonce: {}",
+                            device,
+                            hex::encode_upper(&nonce),
+                        );
+                    }
+                }
+            }
+        }
+    });
+    (sender, handle)
+}
+
+fn count_solution(params: &ThreadParams) {
+    if params.limit != 0
+        && params.found_n.fetch_add(1, atomic::Ordering::Relaxed) + 1 >= params.limit
+    {
+        process::exit(0);
+    }
+}
+
 fn char_to_base32_value(ch: char) -> Option<u8> {
     if ch == '.' || ch == '*' {
         Some(0)
@@ -150,6 +461,1028 @@ fn create_req_mask_for_prefix(prefix: &str) -> (Vec<u8>, Vec<u8>) {
     (req, mask)
 }
 
+/// The key base of the next batch of a CPU thread, with its counter when it
+/// comes from `sweep`. None once the sweep handed out every batch.
+fn next_key_base(sweep: Option<&Sweep>) -> Option<([u8; 32], Option<u64>)> {
+    match sweep {
+        Some(sweep) => sweep.next().map(|(counter, key_base)| (key_base, Some(counter))),
+        None => {
+            let mut key_base = [0u8; 32];
+            OsRng.fill_bytes(&mut key_base);
+            Some((key_base, None))
+        }
+    }
+}
+
+/// Searches random seeds, or the seeds of a new multisig member with
+/// `multisig`. With `sweep`, the seeds of its batches are searched instead.
+fn start_cpu_thread(
+    params: ThreadParams,
+    multisig: Option<&'static multisig::Template>,
+    mnemonic_prefix: Option<mnemonic::Prefix>,
+    sweep: Option<Arc<Sweep>>,
+    output_progress: bool,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut seeds = vec![[0u8; 32]; CPU_BATCH_SIZE];
+        while let Some((mut key_base, counter)) = next_key_base(sweep.as_deref()) {
+            if let Some(prefix) = mnemonic_prefix {
+                prefix.apply(&mut key_base);
+            }
+            // like the GPU, vary the bytes after the ones the mnemonic prefix fixes
+            let base = LittleEndian::read_u64(&key_base[8..]);
+            for (i, seed) in seeds.iter_mut().enumerate() {
+                *seed = key_base;
+                LittleEndian::write_u64(&mut seed[8..], base.wrapping_add(i as u64));
+            }
+            let pubkeys = derivation::ed25519_privkeys_to_pubkeys(&seeds);
+            for (seed, pubkey) in seeds.iter().zip(pubkeys.iter()) {
+                let valid = match multisig {
+                    None => !params.matcher.matches(pubkey) || check_solution(&params, *seed),
+                    Some(template) => {
+                        !params.matcher.matches(&template.address(pubkey))
+                            || check_multisig_solution(&params, template, *seed)
+                    }
+                };
+                if !valid {
+                    eprintln!(
+                        "Batched CPU derivation returned non-matching solution: {}",
+                        hex::encode_upper(seed),
+                    );
+                }
+            }
+            if output_progress {
+                params
+                    .attempts
+                    .fetch_add(CPU_BATCH_SIZE, atomic::Ordering::Relaxed);
+            }
+            if let (Some(sweep), Some(counter)) = (&sweep, counter) {
+                sweep.finish(counter, CPU_BATCH_SIZE);
+            }
+        }
+    })
+}
+
+/// Searches the children of an HD wallet, taking turns with the other devices
+/// through `cursor`
+fn start_cpu_hd_thread(
+    params: ThreadParams,
+    root: hd::Root,
+    cursor: Arc<hd::Cursor>,
+    output_progress: bool,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut chain: Option<(u32, hd::Chain)> = None;
+        loop {
+            let (account, first_index) = cursor.reserve(CPU_BATCH_SIZE);
+            if chain.as_ref().is_none_or(|&(chain_account, _)| chain_account != account) {
+                chain = Some((account, root.account_chain(account)));
+            }
+            let pubkeys = chain.as_ref().unwrap().1.child_pubkeys(first_index, CPU_BATCH_SIZE);
+            for (i, pubkey) in pubkeys.iter().enumerate() {
+                let index = first_index + i as u32;
+                if params.matcher.matches(pubkey) && !check_hd_solution(&params, &root, account, index) {
+                    eprintln!(
+                        "CPU HD derivation returned non-matching solution: {}",
+                        hd::path(account, index),
+                    );
+                }
+            }
+            if output_progress {
+                params
+                    .attempts
+                    .fetch_add(CPU_BATCH_SIZE, atomic::Ordering::Relaxed);
+            }
+        }
+    })
+}
+
+/// Walks consecutive offsets of a split-key request from random starts, or
+/// the starts of `sweep`
+fn start_cpu_split_key_thread(
+    params: ThreadParams,
+    pubkey: [u8; 32],
+    sweep: Option<Arc<Sweep>>,
+    output_progress: bool,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let point = split_key::parse_point(&pubkey).expect("Invalid split-key request");
+        while let Some((mut start, counter)) = next_key_base(sweep.as_deref()) {
+            derivation::clamp_incremental_start(&mut start);
+            let pubkeys = split_key::offset_pubkeys(&point, &start, CPU_BATCH_SIZE);
+            for (i, public_key) in pubkeys.iter().enumerate() {
+                let offset = derivation::incremental_scalar(&start, i as u64);
+                if params.matcher.matches(public_key)
+                    && !check_split_key_solution(&params, &pubkey, offset)
+                {
+                    eprintln!(
+                        "CPU split-key search returned non-matching offset: {}",
+                        hex::encode_upper(offset),
+                    );
+                }
+            }
+            if output_progress {
+                params
+                    .attempts
+                    .fetch_add(CPU_BATCH_SIZE, atomic::Ordering::Relaxed);
+            }
+            if let (Some(sweep), Some(counter)) = (&sweep, counter) {
+                sweep.finish(counter, CPU_BATCH_SIZE);
+            }
+        }
+    })
+}
+
+/// Searches random nonces of a logic signature, or the nonces of `sweep`
+fn start_cpu_logicsig_thread(
+    params: ThreadParams,
+    template: &'static logicsig::Template,
+    sweep: Option<Arc<Sweep>>,
+    output_progress: bool,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        while let Some((nonce_base, counter)) = next_key_base(sweep.as_deref()) {
+            for offset in 0..CPU_BATCH_SIZE as u64 {
+                let nonce = template.nonce_at_offset(&nonce_base, offset);
+                if params.matcher.matches(&template.address(&nonce))
+                    && !check_logicsig_solution(&params, template, &nonce)
+                {
+                    eprintln!(
+                        "CPU logic signature hashing returned non-matching nonce: {}",
+                        hex::encode_upper(&nonce),
+                    );
+                }
+            }
+            if output_progress {
+                params
+                    .attempts
+                    .fetch_add(CPU_BATCH_SIZE, atomic::Ordering::Relaxed);
+            }
+            if let (Some(sweep), Some(counter)) = (&sweep, counter) {
+                sweep.finish(counter, CPU_BATCH_SIZE);
+            }
+        }
+    })
+}
+
+/// Searches random note nonces of a transaction or group, or the nonces of
+/// `sweep`
+fn start_cpu_txid_thread(
+    params: ThreadParams,
+    template: &'static txid::Template,
+    sweep: Option<Arc<Sweep>>,
+    output_progress: bool,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        while let Some((nonce_base, counter)) = next_key_base(sweep.as_deref()) {
+            for offset in 0..CPU_BATCH_SIZE as u64 {
+                let nonce = template.nonce_at_offset(&nonce_base, offset);
+                if params.matcher.matches(&template.id(&nonce))
+                    && !check_txid_solution(&params, template, &nonce)
+                {
+                    eprintln!(
+                        "CPU transaction hashing returned non-matching nonce: {}",
+                        hex::encode_upper(&nonce),
+                    );
+                }
+            }
+            if output_progress {
+                params
+                    .attempts
+                    .fetch_add(CPU_BATCH_SIZE, atomic::Ordering::Relaxed);
+            }
+            if let (Some(sweep), Some(counter)) = (&sweep, counter) {
+                sweep.finish(counter, CPU_BATCH_SIZE);
+            }
+        }
+    })
+}
+
+/// Compares the batched CPU derivation with deriving every key through `ring`
+fn benchmark_cpu() {
+    let mut seeds = vec![[0u8; 32]; 4 * CPU_BATCH_SIZE];
+    for seed in &mut seeds {
+        OsRng.fill_bytes(seed);
+    }
+
+    // warm up both paths before timing them
+    derivation::ed25519_privkey_to_pubkey(&seeds[0]);
+    derivation::ed25519_privkeys_to_pubkeys(&seeds[..1]);
+
+    let start_time = Instant::now();
+    for seed in &seeds {
+        hint::black_box(derivation::ed25519_privkey_to_pubkey(seed));
+    }
+    let ring_runtime = start_time.elapsed();
+
+    let start_time = Instant::now();
+    for chunk in seeds.chunks(CPU_BATCH_SIZE) {
+        hint::black_box(derivation::ed25519_privkeys_to_pubkeys(chunk));
+    }
+    let batched_runtime = start_time.elapsed();
+
+    let ring_keys_per_second = seeds.len() as f64 / ring_runtime.as_secs_f64();
+    let batched_keys_per_second = seeds.len() as f64 / batched_runtime.as_secs_f64();
+    println!("ring:    {:.1} keys/s", ring_keys_per_second);
+    println!(
+        "batched: {:.1} keys/s ({:.2}x)",
+        batched_keys_per_second,
+        batched_keys_per_second / ring_keys_per_second,
+    );
+}
+
+/// A GPU that failed this long ago is forgiven, so its next failure gets the
+/// shortest backoff again
+const GPU_HEALTHY_RESET: Duration = Duration::from_secs(600);
+
+fn gpu_timeout(args: &clap::ArgMatches) -> Duration {
+    let secs: f64 = args
+        .value_of("gpu_timeout")
+        .unwrap()
+        .parse()
+        .expect("Failed to parse GPU timeout option");
+    Duration::from_secs_f64(secs)
+}
+
+/// The HD wallet to search with `--hd-root` or `--hd-mnemonic`, if any
+fn hd_root(args: &clap::ArgMatches) -> Option<hd::Root> {
+    let name = args.value_of("hd_derivation").unwrap();
+    let derivation = hd::Derivation::from_name(name).unwrap_or_else(|| {
+        eprintln!("Unknown HD derivation: {}", name);
+        process::exit(1);
+    });
+    let root = if let Some(key) = args.value_of("hd_root") {
+        hd::Root::from_hex(key, derivation)
+    } else if let Some(mnemonic) = args.value_of("hd_mnemonic") {
+        let mut line = String::new();
+        let mnemonic = if mnemonic == "-" {
+            io::stdin()
+                .read_line(&mut line)
+                .expect("Failed to read the mnemonic from stdin");
+            &line
+        } else {
+            mnemonic
+        };
+        hd::Root::from_mnemonic(mnemonic, args.value_of("hd_passphrase").unwrap_or(""), derivation)
+    } else {
+        return None;
+    };
+    Some(root.unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    }))
+}
+
+/// The logic signature to search with `--logicsig`, if any. It lives as long
+/// as the search, so it is leaked to share it with every device.
+fn logicsig_template(args: &clap::ArgMatches) -> Option<&'static logicsig::Template> {
+    let path = args.value_of("logicsig")?;
+    let program = fs::read(path).unwrap_or_else(|err| {
+        eprintln!("Failed to read the logic signature {}: {}", path, err);
+        process::exit(1);
+    });
+    let placeholder = hex::decode(args.value_of("logicsig_placeholder").unwrap())
+        .unwrap_or_else(|err| {
+            eprintln!("Invalid logic signature placeholder: {}", err);
+            process::exit(1);
+        });
+    let template = logicsig::Template::new(&program, &placeholder).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    Some(Box::leak(Box::new(template)))
+}
+
+/// The transactions to search with `--txid`, if any. Like logic signatures,
+/// the template is leaked to share it with every device.
+fn txid_template(args: &clap::ArgMatches) -> Option<&'static txid::Template> {
+    let path = args.value_of("txid")?;
+    let contents = fs::read(path).unwrap_or_else(|err| {
+        eprintln!("Failed to read the transactions {}: {}", path, err);
+        process::exit(1);
+    });
+    let index = args
+        .value_of("txid_index")
+        .unwrap_or("0")
+        .parse()
+        .expect("Failed to parse transaction index");
+    let template = txid::Template::new(&contents, index).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    Some(Box::leak(Box::new(template)))
+}
+
+/// The first mnemonic words of the seeds to search with `--mnemonic-prefix`,
+/// if any
+fn mnemonic_prefix(args: &clap::ArgMatches) -> Option<mnemonic::Prefix> {
+    let words: Vec<&str> = args.value_of("mnemonic_prefix")?.split_whitespace().collect();
+    Some(mnemonic::Prefix::new(&words).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    }))
+}
+
+/// What turns the key bases of a sweep into keys besides the mode, as parts
+/// of its keyspace
+fn sweep_keyspace(mode: SearchMode, mnemonic_prefix: Option<mnemonic::Prefix>) -> Vec<Vec<u8>> {
+    let position = |pos: usize| (pos as u64).to_le_bytes().to_vec();
+    let mut parts = vec![mnemonic_prefix.map_or(Vec::new(), |prefix| prefix.to_bytes().to_vec())];
+    match mode {
+        SearchMode::Seed | SearchMode::Incremental | SearchMode::Hd(_) => {}
+        SearchMode::LogicSig(template) => {
+            parts.push(template.message().to_vec());
+            parts.push(position(template.nonce_pos()));
+            parts.push(position(template.nonce_len()));
+        }
+        SearchMode::Multisig(template) => {
+            parts.push(template.message().to_vec());
+            parts.push(position(template.member_pos()));
+        }
+        SearchMode::SplitKey(pubkey) => parts.push(pubkey.to_vec()),
+        SearchMode::TxId(template) => {
+            parts.push(template.message().to_vec());
+            parts.push(position(template.tx_len()));
+            parts.push(position(template.nonce_pos()));
+            parts.push(position(template.group_pos()));
+        }
+    }
+    parts
+}
+
+/// The deterministic search of `--sweep-seed`, if any
+fn sweep(
+    args: &clap::ArgMatches,
+    mode: SearchMode,
+    mnemonic_prefix: Option<mnemonic::Prefix>,
+) -> Option<Sweep> {
+    let seed = args.value_of("sweep_seed")?;
+    let mut line = String::new();
+    let seed = if seed == "-" {
+        io::stdin()
+            .read_line(&mut line)
+            .expect("Failed to read the sweep seed from stdin");
+        line.trim()
+    } else {
+        seed
+    };
+    let mut master = [0u8; 32];
+    match hex::decode(seed) {
+        Ok(ref bytes) if bytes.len() == 32 => master.copy_from_slice(bytes),
+        _ => {
+            eprintln!("The sweep seed must be 64 hex characters");
+            process::exit(1);
+        }
+    }
+    let keyspace = sweep_keyspace(mode, mnemonic_prefix);
+    let keyspace: Vec<&[u8]> = keyspace.iter().map(Vec::as_slice).collect();
+    let mode = cache::mode_name(mode);
+    let checkpoint = args.value_of("checkpoint").map(|path| {
+        let interval = args
+            .value_of("checkpoint_interval")
+            .unwrap_or("64")
+            .parse()
+            .ok()
+            .filter(|&interval| interval > 0)
+            .unwrap_or_else(|| {
+                eprintln!("The checkpoint interval must be a positive number of batches");
+                process::exit(1);
+            });
+        (PathBuf::from(path), interval)
+    });
+    let sweep = match checkpoint {
+        Some((path, interval)) if args.is_present("resume") => {
+            Sweep::resume(master, mode, &keyspace, path, interval).unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            })
+        }
+        checkpoint => {
+            if let Some((ref path, _)) = checkpoint {
+                if path.exists() {
+                    eprintln!(
+                        "The checkpoint {} already exists, pass --resume to continue it",
+                        path.display()
+                    );
+                    process::exit(1);
+                }
+            }
+            let range = args.value_of("sweep_range").unwrap_or("0..18446744073709551615");
+            let (start, end) = range
+                .split_once("..")
+                .and_then(|(start, end)| Some((start.parse().ok()?, end.parse().ok()?)))
+                .filter(|&(start, end)| start < end)
+                .unwrap_or_else(|| {
+                    eprintln!("Invalid sweep range {}, expected START..END", range);
+                    process::exit(1);
+                });
+            Sweep::new(master, mode, &keyspace, start, end, checkpoint)
+        }
+    };
+    eprintln!("{}", sweep.summary());
+    Some(sweep)
+}
+
+/// The client's public key to search offsets of with `--split-key`, if any
+fn split_key_pubkey(args: &clap::ArgMatches) -> Option<[u8; 32]> {
+    let request = args.value_of("split_key")?;
+    let mut pubkey = [0u8; 32];
+    let parsed = hex::decode_to_slice(request, &mut pubkey)
+        .map_err(|err| err.to_string())
+        .and_then(|()| split_key::parse_point(&pubkey));
+    if let Err(err) = parsed {
+        eprintln!("Invalid split-key request {}: {}", request, err);
+        process::exit(1);
+    }
+    Some(pubkey)
+}
+
+fn read_split_key_secret(path: &str) -> [u8; 32] {
+    let contents = fs::read_to_string(path).unwrap_or_else(|err| {
+        eprintln!("Failed to read the split-key secret {}: {}", path, err);
+        process::exit(1);
+    });
+    let mut secret = [0u8; 32];
+    if let Err(err) = hex::decode_to_slice(contents.trim(), &mut secret) {
+        eprintln!("Invalid split-key secret in {}: {}", path, err);
+        process::exit(1);
+    }
+    secret
+}
+
+/// Generates the secret of a split-key search and prints the request for the
+/// workers. The secret never leaves the file.
+fn split_key_request(args: &clap::ArgMatches) {
+    let path = args.value_of("secret_file").unwrap();
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let written = options
+        .open(path)
+        .and_then(|mut file| io::Write::write_all(&mut file, format!("{}\n", hex::encode(secret)).as_bytes()));
+    if let Err(err) = written {
+        eprintln!("Failed to write the split-key secret {}: {}", path, err);
+        process::exit(1);
+    }
+    let request = hex::encode(split_key::request(&secret));
+    println!("Split-Key Request: {}", request);
+    println!(
+        "Keep {} to yourself and let the workers search with: --split-key {}",
+        path, request
+    );
+}
+
+/// Adds the offset found by a worker to the secret of the request
+fn split_key_combine(args: &clap::ArgMatches) {
+    let secret = read_split_key_secret(args.value_of("secret_file").unwrap());
+    let mut offset = [0u8; 32];
+    if let Err(err) = hex::decode_to_slice(args.value_of("offset").unwrap(), &mut offset) {
+        eprintln!("Invalid split-key offset: {}", err);
+        process::exit(1);
+    }
+    let expanded_key = split_key::combine(&secret, &offset);
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(&expanded_key[..32]);
+    let public_key = derivation::ed25519_scalar_to_pubkey(&scalar);
+    let point = split_key::parse_point(&split_key::request(&secret)).unwrap();
+    if public_key != split_key::offset_pubkey(&point, &offset) {
+        eprintln!("The combined key does not match the offset");
+        process::exit(1);
+    }
+    println!(
+        "Expanded Secret Key: {} \nAddress: {} \nThis key has no seed or mnemonic, it can only be used with wallets and libraries that accept expanded Ed25519 keys",
+        hex::encode(&expanded_key[..]),
+        derivation::pubkey_to_address(&public_key),
+    );
+}
+
+/// The exit codes of `verify` and `verify-proof` besides 0 for a valid input
+/// that matches
+const VERIFY_INVALID: i32 = 1;
+const VERIFY_NO_MATCH: i32 = 2;
+
+/// Whether `address` starts with `pattern`, where . and * match any character.
+/// Compares the characters rather than the bits the search compares, so it
+/// double-checks the search.
+fn address_matches(address: &str, pattern: &str) -> bool {
+    pattern.len() <= address.len()
+        && pattern
+            .chars()
+            .zip(address.chars())
+            .all(|(p, a)| p == '.' || p == '*' || p == a)
+}
+
+/// Checks a found account with nothing but the derivation, for re-checking
+/// results before handing them over
+fn verify(args: &clap::ArgMatches) {
+    let input = args.value_of("input").unwrap();
+    let input = if input == "-" {
+        let mut line = String::new();
+        io::stdin()
+            .read_line(&mut line)
+            .expect("Failed to read the input from stdin");
+        line
+    } else {
+        input.to_string()
+    };
+    let input = input.trim();
+    let mut hex_seed = [0u8; 32];
+    let parsed = if input.contains(char::is_whitespace) {
+        mnemonic::to_seed(input).map(|seed| (Some(seed), derivation::ed25519_privkey_to_pubkey(&seed)))
+    } else if hex::decode_to_slice(input, &mut hex_seed).is_ok() {
+        Ok((Some(hex_seed), derivation::ed25519_privkey_to_pubkey(&hex_seed)))
+    } else if input.len() == 58 {
+        derivation::address_to_pubkey(input).map(|pubkey| (None, pubkey))
+    } else {
+        // the secret keys of the SDKs: the seed followed by the public key
+        match data_encoding::BASE64.decode(input.as_bytes()) {
+            Ok(ref secret_key) if secret_key.len() == 64 => {
+                let mut seed = [0u8; 32];
+                seed.copy_from_slice(&secret_key[..32]);
+                let pubkey = derivation::ed25519_privkey_to_pubkey(&seed);
+                if secret_key[32..] == pubkey {
+                    Ok((Some(seed), pubkey))
+                } else {
+                    Err("The public key in the secret key does not belong to its seed".to_string())
+                }
+            }
+            _ => Err("Expected a hex seed, a base64 secret key, a 25 word mnemonic or an address".to_string()),
+        }
+    };
+    let (seed, pubkey) = parsed.unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(VERIFY_INVALID);
+    });
+    let address = derivation::pubkey_to_address(&pubkey);
+    if seed.is_some() {
+        println!("Public Key: {} \nAddress: {} ", hex::encode(pubkey), address);
+    } else {
+        println!("Address: {} \nPublic Key: {} \nThe checksum of the address is valid", address, hex::encode(pubkey));
+    }
+    if let Some(pattern) = args.value_of("pattern") {
+        if !address_matches(&address, pattern) {
+            println!("Does not match {}", pattern);
+            process::exit(VERIFY_NO_MATCH);
+        }
+        println!("Matches {}", pattern);
+    }
+}
+
+/// Checks a signature made with `--sign-challenge` against the public key in
+/// the address, with the exit codes of `verify`
+fn verify_proof(args: &clap::ArgMatches) {
+    let address = args.value_of("address").unwrap().trim();
+    let challenge = args.value_of("message").unwrap();
+    let verified = proof::parse_signature(args.value_of("signature").unwrap().trim())
+        .and_then(|signature| proof::verify(address, challenge.as_bytes(), &signature))
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(VERIFY_INVALID);
+        });
+    if !verified {
+        println!("The key of {} did not sign the message", address);
+        process::exit(VERIFY_NO_MATCH);
+    }
+    println!("The key of {} signed the message", address);
+}
+
+/// The multisig account to search with `--multisig`, if any, leaked like the
+/// logic signature template
+fn multisig_template(args: &clap::ArgMatches) -> Option<&'static multisig::Template> {
+    let members = args.values_of("multisig")?;
+    let members: Vec<[u8; 32]> = members
+        .map(|member| {
+            derivation::address_to_pubkey(member).unwrap_or_else(|err| {
+                eprintln!("Invalid multisig member: {}", err);
+                process::exit(1);
+            })
+        })
+        .collect();
+    let threshold = args
+        .value_of("multisig_threshold")
+        .unwrap()
+        .parse()
+        .expect("Failed to parse multisig threshold option");
+    let position = args
+        .value_of("multisig_position")
+        .map(|s| s.parse().expect("Failed to parse multisig position option"))
+        .unwrap_or(members.len());
+    let template = multisig::Template::new(threshold, &members, position).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    Some(Box::leak(Box::new(template)))
+}
+
+/// A launch queued on the GPU
+struct Launch {
+    /// The key root, which is the start scalar in incremental mode, the
+    /// account and first index in HD mode, see `hd::launch_root`, and the
+    /// nonce base in logic signature mode
+    key_root: [u8; 32],
+    /// The planted match of a canary launch
+    plant: Option<canary::Plant>,
+    /// The batch of the sweep the launch searches, if any
+    counter: Option<u64>,
+    keys: usize,
+    started: Instant,
+}
+
+/// Everything the GPU thread needs besides the device itself
+struct GpuContext {
+    mode: SearchMode,
+    /// The first mnemonic words of the seeds in seed and multisig mode
+    mnemonic_prefix: Option<mnemonic::Prefix>,
+    /// Where the next launch starts in HD mode
+    hd_cursor: Arc<hd::Cursor>,
+    /// Hands out the key roots of launches instead of `OsRng`, if given
+    sweep: Option<Arc<Sweep>>,
+    canary: Canary,
+    canary_stop: bool,
+    throttle: Arc<Throttle>,
+    verifier: mpsc::Sender<Candidate>,
+    attempts: Arc<AtomicUsize>,
+    output_progress: bool,
+}
+
+/// Queues a launch, returning false instead once the sweep handed out every
+/// batch
+fn enqueue_launch(
+    gpu: &mut Gpu,
+    ctx: &mut GpuContext,
+    queued: &mut VecDeque<Launch>,
+) -> Result<bool, LaunchError> {
+    let keys = gpu.keys_per_launch();
+    // canary launches only check the planted match, so they never search a
+    // batch of the sweep
+    let canary_due = ctx.canary.due();
+    let mut key_root = [0u8; 32];
+    let mut counter = None;
+    match ctx.sweep {
+        Some(ref sweep) if !canary_due => match sweep.next() {
+            Some((next_counter, key_base)) => {
+                counter = Some(next_counter);
+                key_root = key_base;
+            }
+            None => return Ok(false),
+        },
+        _ => OsRng.fill_bytes(&mut key_root),
+    }
+    if let Some(prefix) = ctx.mnemonic_prefix {
+        prefix.apply(&mut key_root);
+    }
+    let device_root = match ctx.mode {
+        SearchMode::Seed
+        | SearchMode::LogicSig(_)
+        | SearchMode::Multisig(_)
+        | SearchMode::TxId(_) => key_root.to_vec(),
+        SearchMode::Incremental | SearchMode::SplitKey(_) => {
+            derivation::clamp_incremental_start(&mut key_root);
+            derivation::ed25519_scalar_to_pubkey(&key_root).to_vec()
+        }
+        SearchMode::Hd(ref root) => {
+            // canary launches only check the planted match either, so they
+            // take random indices instead of skipping those of the cursor
+            let (account, first_index) = if canary_due {
+                (
+                    OsRng.next_u32() % hd::HARDENED,
+                    OsRng.next_u32() % (hd::HARDENED - keys as u32 + 1),
+                )
+            } else {
+                ctx.hd_cursor.reserve(keys)
+            };
+            key_root = hd::launch_root(account, first_index);
+            root.device_root(account, first_index).to_vec()
+        }
+    };
+    let plant = if canary_due {
+        let offset = OsRng.next_u64() % keys as u64;
+        Some(Canary::plant(ctx.mode, &key_root, keys, offset))
+    } else {
+        None
+    };
+    let enqueued = match plant {
+        Some(ref plant) => gpu.enqueue_canary(&device_root, &plant.matcher),
+        None => gpu.enqueue(&device_root),
+    };
+    if let Err(err) = enqueued {
+        if let (Some(sweep), Some(counter)) = (&ctx.sweep, counter) {
+            sweep.abandon(counter);
+        }
+        return Err(err.into());
+    }
+    queued.push_back(Launch {
+        key_root,
+        plant,
+        counter,
+        keys,
+        started: Instant::now(),
+    });
+    Ok(true)
+}
+
+/// How often `--profile` prints where the GPU time went
+const PROFILE_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Splits `wall` time into kernel time, transfer time and the rest, during
+/// which the device waited for the host
+fn report_timings(timings: &LaunchTimings, wall: Duration) {
+    if timings.launches == 0 {
+        return;
+    }
+    let wall = wall.as_secs_f64();
+    let kernel = timings.kernel.as_secs_f64();
+    let transfers = timings.transfers.as_secs_f64();
+    let per_launch = |time: f64| 1000.0 * time / timings.launches as f64;
+    eprintln!(
+        "\nGPU profile over {:.1}s and {} launches: kernel {:.2} ms/launch ({:.1}%), transfers {:.3} ms/launch ({:.1}%), host overhead {:.1}%",
+        wall,
+        timings.launches,
+        per_launch(kernel),
+        100.0 * kernel / wall,
+        per_launch(transfers),
+        100.0 * transfers / wall,
+        (100.0 * (wall - kernel - transfers) / wall).max(0.0),
+    );
+}
+
+/// Runs the launch pipeline of an OpenCL device until a launch fails. Returns
+/// `Ok` when a failed canary launch stopped the device, or the sweep is done,
+/// instead.
+fn run_gpu_pipeline(gpu: &mut Gpu, ctx: &mut GpuContext) -> Result<(), LaunchError> {
+    let mut queued = VecDeque::with_capacity(2);
+    let result = run_gpu_launches(gpu, ctx, &mut queued);
+    // the launches in flight are lost with the device, other devices search
+    // their batches again
+    if let Some(ref sweep) = ctx.sweep {
+        for launch in queued {
+            if let Some(counter) = launch.counter {
+                sweep.abandon(counter);
+            }
+        }
+    }
+    result
+}
+
+fn run_gpu_launches(
+    gpu: &mut Gpu,
+    ctx: &mut GpuContext,
+    queued: &mut VecDeque<Launch>,
+) -> Result<(), LaunchError> {
+    let max_threads = gpu.work_size().threads;
+    let mut last_report = Instant::now();
+    let mut warned_about_keys = false;
+    let mut sweep_done = false;
+    loop {
+        // A throttled device runs one launch at a time, so it is idle while
+        // the host waits
+        while !sweep_done
+            && gpu.can_enqueue()
+            && (queued.is_empty() || !ctx.throttle.is_active())
+        {
+            sweep_done = !enqueue_launch(gpu, ctx, queued)?;
+        }
+        if queued.is_empty() {
+            return Ok(());
+        }
+        let results = gpu.finish()?;
+        let launch = queued.pop_front().unwrap();
+        if results.keys_checked != launch.keys && !warned_about_keys {
+            eprintln!(
+                "\nWarning: the GPU checked {} keys in a launch of {}",
+                results.keys_checked, launch.keys
+            );
+            warned_about_keys = true;
+        }
+        if last_report.elapsed() >= PROFILE_REPORT_INTERVAL {
+            if let Some(timings) = gpu.take_timings() {
+                report_timings(&timings, last_report.elapsed());
+            }
+            last_report = Instant::now();
+        }
+        // Nothing else is in flight while throttled, and once right after the
+        // limits were lifted, which restores the full launch size
+        if queued.is_empty() {
+            let busy = launch.started.elapsed();
+            let mut work_size = gpu.work_size();
+            let threads = match ctx.throttle.max_latency() {
+                Some(target) => {
+                    let granularity = work_size.local_work_size.unwrap_or(1);
+                    throttle::adapt_threads(work_size.threads, max_threads, granularity, busy, target)
+                }
+                None => max_threads,
+            };
+            if threads != work_size.threads {
+                work_size.threads = threads;
+                gpu.set_work_size(work_size)?;
+            }
+            thread::sleep(ctx.throttle.idle_time(busy));
+        }
+
+        if let Some(plant) = launch.plant {
+            let key_root = &launch.key_root;
+            let keys = results.keys_checked;
+            if let Err(err) = ctx.canary.check(ctx.mode, key_root, &plant, &results.offsets, keys) {
+                eprintln!("\nGPU canary launch failed: {}", err);
+                if ctx.canary_stop {
+                    eprintln!("Stopping the GPU");
+                    return Ok(());
+                }
+            }
+            continue;
+        }
+        if ctx.output_progress {
+            ctx.attempts
+                .fetch_add(results.keys_checked, atomic::Ordering::Relaxed);
+        }
+
+        for offset in results.offsets {
+            let candidate = match ctx.mode {
+                SearchMode::Seed => {
+                    Candidate::Seed(Gpu::seed_at_offset(&launch.key_root, offset))
+                }
+                SearchMode::Incremental => {
+                    let mut expanded_key = [0u8; 64];
+                    expanded_key[..32].copy_from_slice(&derivation::incremental_scalar(
+                        &launch.key_root,
+                        offset,
+                    ));
+                    OsRng.fill_bytes(&mut expanded_key[32..]);
+                    Candidate::ExpandedKey(expanded_key)
+                }
+                SearchMode::Hd(root) => {
+                    let (account, first_index) = hd::parse_launch_root(&launch.key_root);
+                    Candidate::HdKey {
+                        root,
+                        account,
+                        index: first_index + offset as u32,
+                    }
+                }
+                SearchMode::SplitKey(pubkey) => Candidate::SplitKeyOffset {
+                    pubkey,
+                    offset: derivation::incremental_scalar(&launch.key_root, offset),
+                },
+                SearchMode::Multisig(template) => Candidate::MultisigMember {
+                    template,
+                    seed: Gpu::seed_at_offset(&launch.key_root, offset),
+                },
+                SearchMode::LogicSig(template) => Candidate::LogicSig {
+                    template,
+                    nonce: template.nonce_at_offset(&launch.key_root, offset),
+                },
+                SearchMode::TxId(template) => Candidate::TxId {
+                    template,
+                    nonce: template.nonce_at_offset(&launch.key_root, offset),
+                },
+            };
+            ctx.verifier
+                .send(candidate)
+                .expect("Verifier thread stopped");
+        }
+        if let (Some(sweep), Some(counter)) = (&ctx.sweep, launch.counter) {
+            sweep.finish(counter, results.keys_checked);
+        }
+    }
+}
+
+/// Benchmarks the work sizes of the selected OpenCL device and saves the
+/// fastest ones, which later runs on the same device and driver pick up
+fn tune_gpu(args: &clap::ArgMatches, mode: SearchMode) {
+    let gpu_platform = args
+        .value_of("gpu_platform")
+        .unwrap()
+        .parse()
+        .expect("Failed to parse GPU platform index");
+    let gpu_device = args
+        .value_of("gpu_device")
+        .unwrap()
+        .parse()
+        .expect("Failed to parse GPU device index");
+    let gpu_batch_size = args
+        .value_of("gpu_batch_size")
+        .unwrap()
+        .parse()
+        .expect("Failed to parse GPU batch size option");
+    // a pattern no key matches, so every launch does the full amount of work
+    let matcher = PubkeyMatcher::new(vec![0u8; 32], vec![0xffu8; 32]);
+    let mut gpu = Gpu::new(GpuOptions {
+        platform_idx: gpu_platform,
+        device_idx: gpu_device,
+        threads: 1,
+        local_work_size: None,
+        global_work_size: None,
+        matcher: &matcher,
+        mode,
+        batch_size: gpu_batch_size,
+        iterations: mode.default_iterations(),
+        use_tuned_profile: false,
+        launch_timeout: gpu_timeout(args),
+        profile: false,
+        opencl_features: args.value_of("opencl_features"),
+    })
+    .unwrap_or_else(|err| {
+        eprintln!("Failed to initialize the GPU: {}", err);
+        process::exit(1);
+    });
+    let profile = gpu.tune().expect("Failed to tune GPU work sizes");
+    let local_work_size = profile
+        .local_work_size
+        .map(|size| size.to_string())
+        .unwrap_or_else(|| "auto".to_string());
+    println!(
+        "Best work sizes for {} (driver {}): --gpu-threads {} --gpu-local-work-size {} --iterations-per-thread {}",
+        gpu.device_name(),
+        gpu.driver_version(),
+        profile.threads,
+        local_work_size,
+        profile.iterations,
+    );
+    match cache::save_profile(gpu.device_name(), gpu.driver_version(), mode, &profile) {
+        Ok(path) => println!("Saved to {}", path.display()),
+        Err(err) => {
+            eprintln!("Failed to save the tuned work sizes: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(feature = "wgpu")]
+fn start_wgpu_thread(
+    args: &clap::ArgMatches,
+    params: ThreadParams,
+    output_progress: bool,
+) -> thread::JoinHandle<()> {
+    let wgpu_adapter = args
+        .value_of("wgpu_adapter")
+        .unwrap()
+        .parse()
+        .expect("Failed to parse wgpu adapter index");
+    let gpu_threads = args
+        .value_of("gpu_threads")
+        .unwrap()
+        .parse()
+        .expect("Failed to parse GPU threads option");
+    let gpu_local_work_size = args.value_of("gpu_local_work_size").map(|s| {
+        s.parse()
+            .expect("Failed to parse GPU local work size option")
+    });
+    let mut key_base = [0u8; 32];
+    let mut gpu = WgpuGpu::new(GpuOptions {
+        platform_idx: 0,
+        device_idx: wgpu_adapter,
+        threads: gpu_threads,
+        local_work_size: gpu_local_work_size,
+        global_work_size: None,
+        matcher: &params.matcher,
+        mode: SearchMode::Seed,
+        batch_size: 1,
+        iterations: 1,
+        use_tuned_profile: false,
+        launch_timeout: Duration::from_secs(0),
+        profile: false,
+        opencl_features: None,
+    })
+    .unwrap();
+    let attempts = params.attempts.clone();
+    let (verifier, _) = start_verifier_thread(params, "wgpu");
+    thread::spawn(move || {
+        let mut found_private_key = [0u8; 32];
+        loop {
+            OsRng.fill_bytes(&mut key_base);
+            let found = gpu
+                .compute(&mut found_private_key as _, &key_base as _)
+                .expect("Failed to run wgpu computation");
+            if output_progress {
+                attempts.fetch_add(gpu.threads(), atomic::Ordering::Relaxed);
+            }
+            if !found {
+                continue;
+            }
+
+            verifier
+                .send(Candidate::Seed(found_private_key))
+                .expect("Verifier thread stopped");
+            found_private_key.fill(0);
+        }
+    })
+}
+
+#[cfg(not(feature = "wgpu"))]
+fn start_wgpu_thread(
+    _args: &clap::ArgMatches,
+    _params: ThreadParams,
+    _output_progress: bool,
+) -> thread::JoinHandle<()> {
+    eprintln!("This build has no wgpu support, rebuild with `--features wgpu`");
+    process::exit(1);
+}
+
 fn main() {
     let args = clap::App::new("algomania-gpu")
         .version(env!("CARGO_PKG_VERSION"))
@@ -158,13 +1491,104 @@ fn main() {
         .arg(
             clap::Arg::with_name("prefix")
                 .value_name("PREFIX")
-                .required_unless("suffix")
+                .required_unless_one(&["suffix", "benchmark_cpu", "tune", "clear_cache"])
                 .help("The prefix for the address"),
         ).arg(
             clap::Arg::with_name("gpu")
                 .short("g")
                 .long("gpu")
                 .help("Enable use of the GPU through OpenCL"),
+        ).arg(
+            clap::Arg::with_name("wgpu")
+                .long("wgpu")
+                .help("Enable use of the GPU through wgpu (Vulkan, Metal or DX12)"),
+        ).arg(
+            clap::Arg::with_name("incremental")
+                .long("incremental")
+                .help("Walk consecutive points from a random start scalar instead of hashing seeds. Much faster, but found keys are expanded secret keys without a seed or mnemonic (OpenCL only)"),
+        ).arg(
+            clap::Arg::with_name("hd_root")
+                .long("hd-root")
+                .value_name("HEX")
+                .conflicts_with_all(&["hd_mnemonic", "incremental"])
+                .help("Search the accounts m/44'/283'/account'/0/index of an ARC-0052 HD wallet with this 96 byte BIP32-Ed25519 root key (kL, kR and chain code) instead of random seeds"),
+        ).arg(
+            clap::Arg::with_name("hd_mnemonic")
+                .long("hd-mnemonic")
+                .value_name("WORDS")
+                .conflicts_with("incremental")
+                .help("Like --hd-root, with the root key of this BIP39 mnemonic. Pass - to read it from stdin, keeping it out of the shell history."),
+        ).arg(
+            clap::Arg::with_name("hd_passphrase")
+                .long("hd-passphrase")
+                .value_name("PASSPHRASE")
+                .requires("hd_mnemonic")
+                .help("The BIP39 passphrase of the --hd-mnemonic, if the wallet uses one"),
+        ).arg(
+            clap::Arg::with_name("hd_account")
+                .long("hd-account")
+                .value_name("N")
+                .default_value("0")
+                .help("The first HD account to search. Indices start at 0 and the search moves on to the next account once all 2^31 are checked."),
+        ).arg(
+            clap::Arg::with_name("hd_derivation")
+                .long("hd-derivation")
+                .value_name("TYPE")
+                .possible_values(&["peikert", "khovratovich"])
+                .default_value("peikert")
+                .help("The BIP32-Ed25519 flavour of the HD wallet. ARC-0052 wallets use peikert."),
+        ).arg(
+            clap::Arg::with_name("logicsig")
+                .long("logicsig")
+                .value_name("PATH")
+                .requires("logicsig_placeholder")
+                .conflicts_with_all(&["incremental", "hd_root", "hd_mnemonic"])
+                .help("Search a contract account instead of a key: vary a placeholder constant of this compiled TEAL program until the address of the program matches"),
+        ).arg(
+            clap::Arg::with_name("logicsig_placeholder")
+                .long("logicsig-placeholder")
+                .value_name("HEX")
+                .requires("logicsig")
+                .help("The 8 to 32 bytes of the --logicsig program that are replaced with nonces, e.g. the value of a pushbytes the program ignores. They must occur exactly once."),
+        ).arg(
+            clap::Arg::with_name("multisig")
+                .long("multisig")
+                .value_name("ADDRESSES")
+                .use_delimiter(true)
+                .requires("multisig_threshold")
+                .conflicts_with_all(&["incremental", "hd_root", "hd_mnemonic", "logicsig"])
+                .help("Search a multisig account instead of a single key: generate a new member next to these comma separated fixed members until the multisig address matches"),
+        ).arg(
+            clap::Arg::with_name("multisig_threshold")
+                .long("multisig-threshold")
+                .value_name("N")
+                .requires("multisig")
+                .help("The number of --multisig members, counting the new one, that have to sign"),
+        ).arg(
+            clap::Arg::with_name("multisig_position")
+                .long("multisig-position")
+                .value_name("INDEX")
+                .requires("multisig")
+                .help("Where the new member goes in the member list, which changes the address. Defaults to the end."),
+        ).arg(
+            clap::Arg::with_name("threads")
+                .short("t")
+                .long("threads")
+                .value_name("N")
+                .default_value("0")
+                .help("The number of CPU threads to search with"),
+        ).arg(
+            clap::Arg::with_name("benchmark_cpu")
+                .long("benchmark-cpu")
+                .help("Compare the speed of the batched CPU key derivation with ring, then exit"),
+        ).arg(
+            clap::Arg::with_name("tune")
+                .long("tune")
+                .help("Find the fastest GPU work sizes for the selected OpenCL device and search mode, save them for later runs, then exit"),
+        ).arg(
+            clap::Arg::with_name("clear_cache")
+                .long("clear-cache")
+                .help("Delete the compiled OpenCL programs cached by earlier runs, which are rebuilt on the next start"),
         ).arg(
             clap::Arg::with_name("limit")
                 .short("l")
@@ -177,17 +1601,71 @@ fn main() {
                 .long("gpu-threads")
                 .value_name("N")
                 .default_value("1048576")
-                .help("The number of GPU threads to use"),
+                .help("The number of GPU threads to use. Without any work size options, the values saved by --tune are used if there are any."),
+        ).arg(
+            clap::Arg::with_name("gpu_batch_size")
+                .long("gpu-batch-size")
+                .value_name("N")
+                .default_value("8")
+                .help("The number of keys every GPU thread packs with a single field inversion. Larger batches need more private memory."),
+        ).arg(
+            clap::Arg::with_name("iterations_per_thread")
+                .long("iterations-per-thread")
+                .value_name("N")
+                .help("The number of key batches every GPU thread checks per launch [default: 1, or 16 with --incremental] (OpenCL only)"),
+        ).arg(
+            clap::Arg::with_name("canary_interval")
+                .long("canary-interval")
+                .value_name("N")
+                .default_value("256")
+                .help("Make every Nth GPU launch a canary launch, which looks for a throwaway pattern with a planted match to detect a GPU returning garbage (0 to disable, OpenCL only)"),
+        ).arg(
+            clap::Arg::with_name("canary_stop")
+                .long("canary-stop")
+                .help("Stop using the GPU when a canary launch fails, instead of only warning"),
+        ).arg(
+            clap::Arg::with_name("gpu_timeout")
+                .long("gpu-timeout")
+                .value_name("SECONDS")
+                .default_value("30")
+                .help("Treat a GPU launch that takes longer than this as hung, and rebuild the device"),
+        ).arg(
+            clap::Arg::with_name("gpu_max_restarts")
+                .long("gpu-max-restarts")
+                .value_name("N")
+                .default_value("5")
+                .help("Stop using a GPU that failed this many times in a row, while the other devices keep searching"),
+        ).arg(
+            clap::Arg::with_name("max_utilization")
+                .long("max-utilization")
+                .value_name("PERCENT")
+                .default_value("100")
+                .help("Leave the GPU idle between launches, so it is busy at most this fraction of the time (OpenCL only)"),
+        ).arg(
+            clap::Arg::with_name("max_latency")
+                .long("max-latency")
+                .value_name("MS")
+                .default_value("0")
+                .help("Shrink GPU launches until each takes at most this long, so the desktop stays responsive (0 for no limit, OpenCL only)"),
+        ).arg(
+            clap::Arg::with_name("control_file")
+                .long("control-file")
+                .value_name("PATH")
+                .help("Watch this file for lines like \"max-utilization 50\" or \"max-latency 16\" (0 or off for no limit) to change the GPU limits while searching"),
+        ).arg(
+            clap::Arg::with_name("profile")
+                .long("profile")
+                .help("Enable OpenCL profiling and regularly print how GPU time splits into kernel time, transfers and host overhead"),
         ).arg(
             clap::Arg::with_name("gpu_local_work_size")
                 .long("gpu-local-work-size")
                 .value_name("N")
-                .help("The GPU local work size. Increasing it may increase performance. For advanced users only."),
+                .help("The GPU local work size. Increasing it may increase performance. Prefer --tune to finding it by hand."),
         ).arg(
-            clap::Arg::with_name("gpu_global_work-size")
+            clap::Arg::with_name("gpu_global_work_size")
                 .long("gpu-global-work-size")
                 .value_name("N")
-                .help("The GPU global work size. Increasing it may increase performance. For advanced users only."),
+                .help("The GPU global work size, overriding --gpu-threads. Prefer --tune to finding it by hand."),
         ).arg(
             clap::Arg::with_name("no_progress")
                 .long("no-progress")
@@ -204,7 +1682,190 @@ fn main() {
                 .value_name("INDEX")
                 .default_value("0")
                 .help("The GPU device to use"),
-        ).get_matches();
+        ).arg(
+            clap::Arg::with_name("opencl_features")
+                .long("opencl-features")
+                .value_name("LIST")
+                .help("Override the OpenCL features probed from the device, for drivers that misreport them. A comma separated list of generic and printf, each optionally prefixed with no-, e.g. no-generic"),
+        ).arg(
+            clap::Arg::with_name("wgpu_adapter")
+                .long("wgpu-adapter")
+                .value_name("INDEX")
+                .default_value("0")
+                .help("The wgpu adapter to use"),
+        ).arg(
+            clap::Arg::with_name("split_key")
+                .long("split-key")
+                .value_name("HEX")
+                .conflicts_with_all(&["incremental", "hd_root", "hd_mnemonic", "logicsig", "multisig"])
+                .help("Search for someone else: find an offset of this split-key request, made with split-key-request, without learning the secret key of the address"),
+        ).arg(
+            clap::Arg::with_name("txid")
+                .long("txid")
+                .value_name("PATH")
+                .conflicts_with_all(&["incremental", "hd_root", "hd_mnemonic", "logicsig", "multisig", "split_key"])
+                .help("Search a transaction ID instead of an address: append a nonce to the note of the unsigned transaction in this file, msgpack or JSON, until its ID matches. With several transactions, the group ID is searched instead."),
+        ).arg(
+            clap::Arg::with_name("txid_index")
+                .long("txid-index")
+                .value_name("N")
+                .requires("txid")
+                .help("The transaction of a --txid group whose note is changed [default: 0]"),
+        ).arg(
+            clap::Arg::with_name("mnemonic_prefix")
+                .long("mnemonic-prefix")
+                .value_name("WORDS")
+                .conflicts_with_all(&["incremental", "hd_root", "hd_mnemonic", "logicsig", "split_key", "txid"])
+                .help("Only try seeds whose mnemonic starts with these 1 to 3 space separated words. The words only fix the leading bits of the seed, so the search is as fast as without them."),
+        ).arg(
+            clap::Arg::with_name("sweep_seed")
+                .long("sweep-seed")
+                .value_name("HEX")
+                .conflicts_with_all(&["incremental", "hd_root", "hd_mnemonic", "wgpu"])
+                .help("Derive the key base of every batch from this 32 byte master seed and the batch number instead of picking it at random, so the search can be audited, split between workers and resumed. Pass - to read it from stdin. Anyone who knows it can find the keys again. Search with either the GPU or CPU threads, not both."),
+        ).arg(
+            clap::Arg::with_name("sweep_range")
+                .long("sweep-range")
+                .value_name("START..END")
+                .requires("sweep_seed")
+                .conflicts_with("resume")
+                .help("The batches to search with --sweep-seed. Give every worker a disjoint range. [default: 0..18446744073709551615]"),
+        ).arg(
+            clap::Arg::with_name("checkpoint")
+                .long("checkpoint")
+                .value_name("PATH")
+                .requires("sweep_seed")
+                .help("Save which batches of the --sweep-seed range were searched to this file. It must not exist yet, unless with --resume."),
+        ).arg(
+            clap::Arg::with_name("checkpoint_interval")
+                .long("checkpoint-interval")
+                .value_name("N")
+                .requires("checkpoint")
+                .help("Save the checkpoint every N finished batches [default: 64]"),
+        ).arg(
+            clap::Arg::with_name("resume")
+                .long("resume")
+                .requires("checkpoint")
+                .help("Continue the sweep saved to --checkpoint, with its range"),
+        ).arg(
+            clap::Arg::with_name("sign_challenge")
+                .long("sign-challenge")
+                .value_name("MESSAGE")
+                .conflicts_with_all(&["logicsig", "split_key", "txid"])
+                .help("Sign this message with every key found and print the signature with the address, to prove holding the key without handing it over. Like the signBytes of the Algorand SDKs, \"MX\" is put before the message. Check the signature with verify-proof."),
+        ).subcommand(
+            clap::SubCommand::with_name("split-key-request")
+                .about("Make a request for a split-key search, which workers can run without learning the secret key")
+                .arg(
+                    clap::Arg::with_name("secret_file")
+                        .long("secret-file")
+                        .value_name("PATH")
+                        .required(true)
+                        .help("Where to save the secret of the request. It must not exist yet."),
+                ),
+        ).subcommand(
+            clap::SubCommand::with_name("split-key-combine")
+                .about("Combine the secret of a split-key request with the offset a worker found")
+                .arg(
+                    clap::Arg::with_name("secret_file")
+                        .long("secret-file")
+                        .value_name("PATH")
+                        .required(true)
+                        .help("The secret saved by split-key-request"),
+                ).arg(
+                    clap::Arg::with_name("offset")
+                        .long("offset")
+                        .value_name("HEX")
+                        .required(true)
+                        .help("The key offset the worker found"),
+                ),
+        ).subcommand(
+            clap::SubCommand::with_name("verify")
+                .about("Derive the address of a seed, secret key or mnemonic, or check the checksum of an address, independently of the search")
+                .after_help("Exits with 0 if the input is valid and matches, 1 if it is invalid and 2 if it does not match the pattern.")
+                .arg(
+                    clap::Arg::with_name("input")
+                        .value_name("INPUT")
+                        .required(true)
+                        .help("A hex seed, a base64 secret key, a 25 word mnemonic in quotes or an address. Pass - to read it from stdin, keeping it out of the shell history."),
+                ).arg(
+                    clap::Arg::with_name("pattern")
+                        .long("pattern")
+                        .value_name("PREFIX")
+                        .help("Check that the address starts with this prefix, where . and * match any character"),
+                ),
+        ).subcommand(
+            clap::SubCommand::with_name("verify-proof")
+                .about("Check that the key of an address signed a challenge message with --sign-challenge, or the signBytes of the Algorand SDKs")
+                .after_help("Exits with 0 if the signature is valid, 1 if the input is invalid and 2 if the key of the address did not sign the message.")
+                .arg(
+                    clap::Arg::with_name("address")
+                        .long("address")
+                        .value_name("ADDRESS")
+                        .required(true)
+                        .help("The address whose key signed the message"),
+                ).arg(
+                    clap::Arg::with_name("message")
+                        .long("message")
+                        .value_name("MESSAGE")
+                        .required(true)
+                        .help("The challenge message, without the \"MX\" prefix"),
+                ).arg(
+                    clap::Arg::with_name("signature")
+                        .long("signature")
+                        .value_name("SIGNATURE")
+                        .required(true)
+                        .help("The signature, in base64 or hex"),
+                ),
+        ).setting(clap::AppSettings::SubcommandsNegateReqs)
+        .get_matches();
+
+    match args.subcommand() {
+        ("split-key-request", Some(sub_args)) => return split_key_request(sub_args),
+        ("split-key-combine", Some(sub_args)) => return split_key_combine(sub_args),
+        ("verify", Some(sub_args)) => return verify(sub_args),
+        ("verify-proof", Some(sub_args)) => return verify_proof(sub_args),
+        _ => (),
+    }
+
+    if args.is_present("clear_cache") {
+        match cache::clear_programs() {
+            Ok(Some(dir)) => eprintln!("Cleared the OpenCL program cache in {}", dir.display()),
+            Ok(None) => eprintln!("The OpenCL program cache is already empty"),
+            Err(err) => {
+                eprintln!("Failed to clear the OpenCL program cache: {}", err);
+                process::exit(1);
+            }
+        }
+        if !args.is_present("prefix") {
+            return;
+        }
+    }
+
+    if args.is_present("benchmark_cpu") {
+        benchmark_cpu();
+        return;
+    }
+
+    let mode = if args.is_present("incremental") {
+        SearchMode::Incremental
+    } else if let Some(pubkey) = split_key_pubkey(&args) {
+        SearchMode::SplitKey(pubkey)
+    } else if let Some(root) = hd_root(&args) {
+        SearchMode::Hd(root)
+    } else if let Some(template) = logicsig_template(&args) {
+        SearchMode::LogicSig(template)
+    } else if let Some(template) = multisig_template(&args) {
+        SearchMode::Multisig(template)
+    } else if let Some(template) = txid_template(&args) {
+        SearchMode::TxId(template)
+    } else {
+        SearchMode::Seed
+    };
+    if args.is_present("tune") {
+        tune_gpu(&args, mode);
+        return;
+    }
 
     let ext_pubkey_req: Vec<u8>;
     let ext_pubkey_mask: Vec<u8>;
@@ -220,6 +1881,12 @@ fn main() {
             eprintln!("Only the first 58 characters of your prefix will be used.");
             eprintln!("");
         }
+        if let SearchMode::TxId(_) = mode {
+            if prefix.chars().count() > 52 {
+                eprintln!("Transaction and group IDs only have 52 characters");
+                process::exit(1);
+            }
+        }
     } else {
         eprintln!("You must specify a non-empty prefix");
         process::exit(1);
@@ -236,6 +1903,45 @@ fn main() {
     let found_n_base = Arc::new(AtomicUsize::new(0));
     let attempts_base = Arc::new(AtomicUsize::new(0));
     let output_progress = !args.is_present("no_progress");
+    let mnemonic_prefix = mnemonic_prefix(&args);
+    // the wgpu backend only searches plain seeds so far
+    if args.is_present("wgpu") && (mode != SearchMode::Seed || mnemonic_prefix.is_some()) {
+        if mnemonic_prefix.is_some() {
+            eprintln!("--mnemonic-prefix is not supported by the wgpu backend yet");
+        } else {
+            eprintln!(
+                "The {} mode is not supported by the wgpu backend yet",
+                cache::mode_name(mode)
+            );
+        }
+        process::exit(1);
+    }
+    let cpu_threads: usize = args
+        .value_of("threads")
+        .unwrap()
+        .parse()
+        .expect("Failed to parse threads option");
+    // a CPU batch checks far fewer keys than a GPU batch, so mixing them would
+    // lower how many keys the sweep can vouch for each batch to the CPU's
+    if args.is_present("sweep_seed") && args.is_present("gpu") && cpu_threads > 0 {
+        eprintln!("--sweep-seed can't be used with both the GPU and CPU threads");
+        process::exit(1);
+    }
+    let sweep = sweep(&args, mode, mnemonic_prefix).map(Arc::new);
+    let challenge: Option<&'static [u8]> = args
+        .value_of("sign_challenge")
+        .map(|challenge| &*Box::leak(challenge.as_bytes().to_vec().into_boxed_slice()));
+    let hd_account: u32 = args
+        .value_of("hd_account")
+        .unwrap()
+        .parse()
+        .ok()
+        .filter(|&account| account < hd::HARDENED)
+        .unwrap_or_else(|| {
+            eprintln!("The HD account must be below {}", hd::HARDENED);
+            process::exit(1);
+        });
+    let hd_cursor = Arc::new(hd::Cursor::new(hd_account));
     //let simple_output = args.is_present("simple_output");
     let mut gpu_thread = None;
     if args.is_present("gpu") {
@@ -254,57 +1960,195 @@ fn main() {
             .unwrap()
             .parse()
             .expect("Failed to parse GPU threads option");
+        let gpu_batch_size = args
+            .value_of("gpu_batch_size")
+            .unwrap()
+            .parse()
+            .expect("Failed to parse GPU batch size option");
+        let gpu_iterations = args
+            .value_of("iterations_per_thread")
+            .map(|s| s.parse().expect("Failed to parse iterations per thread option"))
+            .unwrap_or_else(|| mode.default_iterations());
         let gpu_local_work_size = args.value_of("gpu_local_work_size").map(|s| {
             s.parse()
                 .expect("Failed to parse GPU local work size option")
         });
         let gpu_global_work_size = args.value_of("gpu_global_work_size").map(|s| {
             s.parse()
-                .expect("Failed to parse GPU local work size option")
+                .expect("Failed to parse GPU global work size option")
         });
-        let mut key_base = [0u8; 32];
+        // explicit work sizes win over the ones saved by --tune
+        let use_tuned_profile = [
+            "gpu_threads",
+            "gpu_local_work_size",
+            "gpu_global_work_size",
+            "iterations_per_thread",
+        ]
+        .iter()
+        .all(|name| args.occurrences_of(name) == 0);
+        let canary = Canary::new(
+            args.value_of("canary_interval")
+                .unwrap()
+                .parse()
+                .expect("Failed to parse canary interval option"),
+        );
+        let canary_stop = args.is_present("canary_stop");
+        let gpu_timeout = gpu_timeout(&args);
+        let profile = args.is_present("profile");
+        let opencl_features = args.value_of("opencl_features").map(String::from);
+        let throttle = Arc::new(
+            Throttle::new(
+                args.value_of("max_utilization")
+                    .unwrap()
+                    .trim_end_matches('%')
+                    .parse()
+                    .expect("Failed to parse max utilization option"),
+                args.value_of("max_latency")
+                    .unwrap()
+                    .parse()
+                    .expect("Failed to parse max latency option"),
+            )
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            }),
+        );
+        if let Some(path) = args.value_of("control_file") {
+            throttle::watch_control_file(path.into(), throttle.clone());
+        }
+        let max_restarts: u32 = args
+            .value_of("gpu_max_restarts")
+            .unwrap()
+            .parse()
+            .expect("Failed to parse GPU max restarts option");
         let params = ThreadParams {
             limit,
             matcher: matcher_base.clone(),
             found_n: found_n_base.clone(),
             attempts: attempts_base.clone(),
+            challenge,
         };
-        let mut gpu = Gpu::new(GpuOptions {
-            platform_idx: gpu_platform,
-            device_idx: gpu_device,
-            threads: gpu_threads,
-            local_work_size: gpu_local_work_size,
-            global_work_size: gpu_global_work_size,
-            matcher: &params.matcher,
-        })
-        .unwrap();
-        gpu_thread = Some(thread::spawn(move || {
-            let mut found_private_key = [0u8; 32];
+        let matcher = params.matcher.clone();
+        let new_gpu = move || {
+            Gpu::new(GpuOptions {
+                platform_idx: gpu_platform,
+                device_idx: gpu_device,
+                threads: gpu_threads,
+                local_work_size: gpu_local_work_size,
+                global_work_size: gpu_global_work_size,
+                matcher: &matcher,
+                mode,
+                batch_size: gpu_batch_size,
+                iterations: gpu_iterations,
+                use_tuned_profile,
+                launch_timeout: gpu_timeout,
+                profile,
+                opencl_features: opencl_features.as_deref(),
+            })
+        };
+        let gpu = new_gpu().unwrap_or_else(|err| {
+            eprintln!("Failed to initialize the GPU: {}", err);
+            process::exit(1);
+        });
+        let attempts = params.attempts.clone();
+        let (verifier, verifier_thread) = start_verifier_thread(params, "GPU");
+        let mut ctx = GpuContext {
+            mode,
+            mnemonic_prefix,
+            hd_cursor: hd_cursor.clone(),
+            sweep: sweep.clone(),
+            canary,
+            canary_stop,
+            throttle: throttle.clone(),
+            verifier,
+            attempts,
+            output_progress,
+        };
+        gpu_thread = Some((verifier_thread, thread::spawn(move || {
+            let mut gpu = Some(gpu);
+            let mut failures = 0;
+            let mut healthy_since = Instant::now();
             loop {
-                OsRng.fill_bytes(&mut key_base);
-                let found = gpu
-                    .compute(&mut found_private_key as _, &key_base as _)
-                    .expect("Failed to run GPU computation");
-                if output_progress {
-                    params
-                        .attempts
-                        .fetch_add(gpu_threads, atomic::Ordering::Relaxed);
-                }
-                if !found {
-                    continue;
+                let err = match run_gpu_pipeline(gpu.as_mut().unwrap(), &mut ctx) {
+                    Ok(()) => return,
+                    Err(err) => err,
+                };
+                // release the broken context before building a new one
+                gpu = None;
+                eprintln!("\nGPU launch failed: {}", err);
+                if !err.recoverable() {
+                    eprintln!("The error is not recoverable, removing the GPU");
+                    return;
                 }
-
-                if !check_solution(&params, found_private_key) {
-                    eprintln!(
-                        "GPU returned non-matching solution: {}",
-                        hex::encode_upper(&found_private_key),
-                    );
+                if healthy_since.elapsed() >= GPU_HEALTHY_RESET {
+                    failures = 0;
                 }
-                for byte in &mut found_private_key {
-                    *byte = 0;
+                while gpu.is_none() {
+                    failures += 1;
+                    if failures > max_restarts {
+                        eprintln!("The GPU failed {} times in a row, removing it", failures);
+                        return;
+                    }
+                    let backoff = Duration::from_secs(1 << (failures - 1).min(6));
+                    eprintln!("Rebuilding the GPU in {} seconds", backoff.as_secs());
+                    thread::sleep(backoff);
+                    match new_gpu() {
+                        Ok(new_gpu) => gpu = Some(new_gpu),
+                        Err(err) => {
+                            eprintln!("Failed to rebuild the GPU: {}", err);
+                            if !gpu::is_recoverable(&err) {
+                                eprintln!("The error is not recoverable, removing the GPU");
+                                return;
+                            }
+                        }
+                    }
                 }
+                healthy_since = Instant::now();
             }
-        }));
+        })));
+    }
+    let mut wgpu_thread = None;
+    if args.is_present("wgpu") {
+        let params = ThreadParams {
+            limit,
+            matcher: matcher_base.clone(),
+            found_n: found_n_base.clone(),
+            attempts: attempts_base.clone(),
+            challenge,
+        };
+        wgpu_thread = Some(start_wgpu_thread(&args, params, output_progress));
+    }
+    let mut cpu_thread_handles = Vec::with_capacity(cpu_threads);
+    for _ in 0..cpu_threads {
+        let params = ThreadParams {
+            limit,
+            matcher: matcher_base.clone(),
+            found_n: found_n_base.clone(),
+            attempts: attempts_base.clone(),
+            challenge,
+        };
+        cpu_thread_handles.push(match mode {
+            SearchMode::Hd(root) => {
+                start_cpu_hd_thread(params, root, hd_cursor.clone(), output_progress)
+            }
+            SearchMode::LogicSig(template) => {
+                start_cpu_logicsig_thread(params, template, sweep.clone(), output_progress)
+            }
+            SearchMode::Multisig(template) => start_cpu_thread(
+                params,
+                Some(template),
+                mnemonic_prefix,
+                sweep.clone(),
+                output_progress,
+            ),
+            SearchMode::SplitKey(pubkey) => {
+                start_cpu_split_key_thread(params, pubkey, sweep.clone(), output_progress)
+            }
+            SearchMode::TxId(template) => {
+                start_cpu_txid_thread(params, template, sweep.clone(), output_progress)
+            }
+            _ => start_cpu_thread(params, None, mnemonic_prefix, sweep.clone(), output_progress),
+        });
     }
     if output_progress {
         let start_time = Instant::now();
@@ -324,9 +2168,26 @@ fn main() {
             thread::sleep(Duration::from_millis(250));
         });
     }
-    if let Some(gpu_thread) = gpu_thread {
+    let any_devices = gpu_thread.is_some() || wgpu_thread.is_some() || !cpu_thread_handles.is_empty();
+    if let Some((verifier_thread, gpu_thread)) = gpu_thread {
         gpu_thread.join().expect("Failed to join GPU thread");
+        // the last launches may still have candidates to check
+        verifier_thread.join().expect("Failed to join GPU verifier thread");
+    }
+    if let Some(wgpu_thread) = wgpu_thread {
+        wgpu_thread.join().expect("Failed to join wgpu thread");
+    }
+    for handle in cpu_thread_handles {
+        handle.join().expect("Failed to join CPU thread");
+    }
+    if let Some(sweep) = sweep.filter(|sweep| sweep.complete()) {
+        eprintln!("\nThe sweep is complete. {}", sweep.summary());
+        return;
+    }
+    if any_devices {
+        eprintln!("\nAll computation devices stopped");
+    } else {
+        eprintln!("No computation devices specified");
     }
-    eprintln!("No computation devices specified");
     process::exit(1);
 }
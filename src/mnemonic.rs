@@ -0,0 +1,167 @@
+use byteorder::{ByteOrder, LittleEndian};
+use sha2::{Digest, Sha512_256};
+
+use wordlist;
+
+/// The bits of the seed every mnemonic word encodes
+const BITS_PER_WORD: usize = 11;
+
+/// The words encoding the seed, which are followed by a checksum word
+const SEED_WORDS: usize = 24;
+
+/// The most mnemonic words a search can fix. Their bits stay below the 8th
+/// byte of the seed, where the search varies it.
+pub const MAX_PREFIX_WORDS: usize = 3;
+
+/// The first words of the mnemonic of the seeds a search tries. The words
+/// encode the seed 11 bits at a time, starting with the least significant
+/// bits of its first byte, so they fix the leading bits of the seed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Prefix {
+    bits: u64,
+    mask: u64,
+}
+
+impl Prefix {
+    pub fn new(words: &[&str]) -> Result<Prefix, String> {
+        if words.is_empty() || words.len() > MAX_PREFIX_WORDS {
+            return Err(format!(
+                "Between 1 and {} mnemonic words can be chosen, not {}",
+                MAX_PREFIX_WORDS,
+                words.len()
+            ));
+        }
+        let mut bits = 0;
+        for (i, word) in words.iter().enumerate() {
+            let index = wordlist::index_of(word)
+                .ok_or_else(|| format!("{} is not a mnemonic word", word))?;
+            bits |= (index as u64) << (BITS_PER_WORD * i);
+        }
+        Ok(Prefix {
+            bits,
+            mask: (1 << (BITS_PER_WORD * words.len())) - 1,
+        })
+    }
+
+    /// The bits and mask of the words, which identify the prefix
+    pub fn to_bytes(self) -> [u8; 16] {
+        let mut bytes = [0u8; 16];
+        LittleEndian::write_u64(&mut bytes[..8], self.bits);
+        LittleEndian::write_u64(&mut bytes[8..], self.mask);
+        bytes
+    }
+
+    /// Replaces the leading bits of `seed` with those of the words
+    pub fn apply(&self, seed: &mut [u8; 32]) {
+        let leading = LittleEndian::read_u64(seed);
+        LittleEndian::write_u64(seed, (leading & !self.mask) | self.bits);
+    }
+}
+
+/// Splits `bytes` into 11 bit word indices, least significant bits first.
+/// The last index holds the remaining bits.
+fn to_indices(bytes: &[u8]) -> Vec<usize> {
+    let mut indices = Vec::new();
+    let mut buffer = 0;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer |= (byte as usize) << bits;
+        bits += 8;
+        if bits >= BITS_PER_WORD {
+            indices.push(buffer & 0x7ff);
+            buffer >>= BITS_PER_WORD;
+            bits -= BITS_PER_WORD;
+        }
+    }
+    if bits != 0 {
+        indices.push(buffer);
+    }
+    indices
+}
+
+/// The word of the checksum: the first 11 bits of the seed's SHA-512/256
+fn checksum_index(seed: &[u8; 32]) -> usize {
+    to_indices(&Sha512_256::digest(seed)[..2])[0]
+}
+
+/// The 25 word mnemonic of a seed, as Algorand wallets show it
+pub fn from_seed(seed: &[u8; 32]) -> String {
+    let words = wordlist::words();
+    let mut mnemonic: Vec<&str> = to_indices(seed).into_iter().map(|i| words[i]).collect();
+    mnemonic.push(words[checksum_index(seed)]);
+    mnemonic.join(" ")
+}
+
+/// The seed of a 25 word mnemonic, if its checksum word is right
+pub fn to_seed(mnemonic: &str) -> Result<[u8; 32], String> {
+    let indices = mnemonic
+        .split_whitespace()
+        .map(|word| wordlist::index_of(word).ok_or_else(|| format!("{} is not a mnemonic word", word)))
+        .collect::<Result<Vec<usize>, String>>()?;
+    if indices.len() != SEED_WORDS + 1 {
+        return Err(format!(
+            "A mnemonic has {} words, not {}",
+            SEED_WORDS + 1,
+            indices.len()
+        ));
+    }
+    // the 24 words hold 264 bits, the last 8 must be zero
+    let mut bytes = Vec::with_capacity(33);
+    let mut buffer = 0;
+    let mut bits = 0;
+    for &index in &indices[..SEED_WORDS] {
+        buffer |= index << bits;
+        bits += BITS_PER_WORD;
+        while bits >= 8 {
+            bytes.push(buffer as u8);
+            buffer >>= 8;
+            bits -= 8;
+        }
+    }
+    if bytes[32] != 0 {
+        return Err("The mnemonic does not encode a 32 byte seed".to_string());
+    }
+    let mut seed = [0u8; 32];
+    seed.copy_from_slice(&bytes[..32]);
+    if indices[SEED_WORDS] != checksum_index(&seed) {
+        return Err("The checksum word of the mnemonic is wrong".to_string());
+    }
+    Ok(seed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefix() {
+        let prefix = Prefix::new(&["zoo", "abandon", "ability"]).unwrap();
+        let mut seed = [0xffu8; 32];
+        prefix.apply(&mut seed);
+        // 2047, then 0, then 1, packed 11 bits at a time
+        assert_eq!(seed[..5], [0xff, 0x07, 0x40, 0x00, 0xfe]);
+        assert_eq!(seed[5..], [0xffu8; 27]);
+
+        assert!(Prefix::new(&[]).is_err());
+        assert!(Prefix::new(&["zoo", "zoo", "zoo", "zoo"]).is_err());
+        assert!(Prefix::new(&["zzz"]).is_err());
+    }
+
+    #[test]
+    fn test_mnemonic() {
+        // generated with algonaut's Account::from_seed
+        let zeros = format!("{} invest", vec!["abandon"; 24].join(" "));
+        let ones = format!("{} abstract adapt", vec!["zoo"; 23].join(" "));
+        assert_eq!(from_seed(&[0u8; 32]), zeros);
+        assert_eq!(from_seed(&[0xffu8; 32]), ones);
+        assert_eq!(to_seed(&zeros), Ok([0u8; 32]));
+        assert_eq!(to_seed(&ones), Ok([0xffu8; 32]));
+
+        let seed = [0x5au8; 32];
+        assert_eq!(to_seed(&from_seed(&seed)), Ok(seed));
+        assert!(to_seed(&zeros.replace("invest", "abandon")).is_err());
+        assert!(to_seed(&zeros.replace(" invest", "")).is_err());
+        // the 24th word carries 3 bits past the seed
+        assert!(to_seed(&format!("{} zoo invest", vec!["abandon"; 23].join(" "))).is_err());
+    }
+}
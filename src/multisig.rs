@@ -0,0 +1,126 @@
+use sha2::{Digest, Sha512_256};
+
+/// The domain separation prefix of multisig addresses
+const MULTISIG_PREFIX: &[u8] = b"MultisigAddr";
+
+/// The only multisig version the protocol knows
+pub const VERSION: u8 = 1;
+
+/// The preimage of a multisig address with a slot for one new member, whose
+/// key the search varies until the multisig address matches
+#[derive(PartialEq, Eq, Debug)]
+pub struct Template {
+    /// "MultisigAddr" || version || threshold || public keys, with zeros in
+    /// the slot of the new member
+    message: Vec<u8>,
+    /// The index of the new member
+    position: usize,
+}
+
+impl Template {
+    /// `members` are the public keys of the fixed members, in order. The new
+    /// member is inserted before the member at `position`.
+    pub fn new(threshold: u8, members: &[[u8; 32]], position: usize) -> Result<Template, String> {
+        let count = members.len() + 1;
+        if count > u8::MAX as usize {
+            return Err(format!("A multisig account has at most {} members", u8::MAX));
+        }
+        if threshold == 0 || threshold as usize > count {
+            return Err(format!(
+                "The threshold must be between 1 and the {} members",
+                count
+            ));
+        }
+        if position > members.len() {
+            return Err(format!(
+                "The new member can't be at position {} of {} members",
+                position, count
+            ));
+        }
+        let mut message = MULTISIG_PREFIX.to_vec();
+        message.push(VERSION);
+        message.push(threshold);
+        for member in &members[..position] {
+            message.extend_from_slice(member);
+        }
+        message.extend_from_slice(&[0u8; 32]);
+        for member in &members[position..] {
+            message.extend_from_slice(member);
+        }
+        Ok(Template { message, position })
+    }
+
+    /// The preimage with zeros in the slot of the new member
+    pub fn message(&self) -> &[u8] {
+        &self.message
+    }
+
+    /// Where the public key of the new member starts in `message`
+    pub fn member_pos(&self) -> usize {
+        MULTISIG_PREFIX.len() + 2 + 32 * self.position
+    }
+
+    pub fn threshold(&self) -> u8 {
+        self.message[MULTISIG_PREFIX.len() + 1]
+    }
+
+    /// The public keys of all members, with `pubkey` as the new one
+    pub fn members(&self, pubkey: &[u8; 32]) -> Vec<[u8; 32]> {
+        self.preimage(pubkey)[MULTISIG_PREFIX.len() + 2..]
+            .chunks(32)
+            .map(|chunk| {
+                let mut member = [0u8; 32];
+                member.copy_from_slice(chunk);
+                member
+            })
+            .collect()
+    }
+
+    /// The preimage of the multisig address with `pubkey` as the new member
+    pub fn preimage(&self, pubkey: &[u8; 32]) -> Vec<u8> {
+        let mut preimage = self.message.clone();
+        let pos = self.member_pos();
+        preimage[pos..pos + 32].copy_from_slice(pubkey);
+        preimage
+    }
+
+    /// The multisig address with `pubkey` as the new member, without the
+    /// checksum
+    pub fn address(&self, pubkey: &[u8; 32]) -> [u8; 32] {
+        let pos = self.member_pos();
+        let mut address = [0u8; 32];
+        address.copy_from_slice(
+            &Sha512_256::new()
+                .chain_update(&self.message[..pos])
+                .chain_update(pubkey)
+                .chain_update(&self.message[pos + 32..])
+                .finalize(),
+        );
+        address
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_template() {
+        let members = [[1u8; 32], [2u8; 32]];
+        let template = Template::new(2, &members, 1).unwrap();
+        let pubkey = [3u8; 32];
+        assert_eq!(template.threshold(), 2);
+        assert_eq!(template.members(&pubkey), [[1u8; 32], [3u8; 32], [2u8; 32]]);
+
+        let mut preimage = b"MultisigAddr\x01\x02".to_vec();
+        preimage.extend_from_slice(&[1u8; 32]);
+        preimage.extend_from_slice(&[3u8; 32]);
+        preimage.extend_from_slice(&[2u8; 32]);
+        assert_eq!(template.preimage(&pubkey), preimage);
+        assert_eq!(template.address(&pubkey)[..], Sha512_256::digest(&preimage)[..]);
+
+        assert!(Template::new(0, &members, 0).is_err());
+        assert!(Template::new(4, &members, 0).is_err());
+        assert!(Template::new(1, &members, 3).is_err());
+    }
+}
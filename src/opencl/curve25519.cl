@@ -1384,6 +1384,18 @@ ge25519_pack(unsigned char r[32], const NAMESPACE_QUALIFIER ge25519 *p) {
 	r[31] ^= ((parity[0] & 1) << 7);
 }
 
+/* ge25519_pack with a precomputed zi = 1/z, so callers can share one inversion between several points */
+static void
+ge25519_pack_zi(unsigned char r[32], const NAMESPACE_QUALIFIER ge25519 *p, const NAMESPACE_QUALIFIER bignum25519 zi) {
+	bignum25519 tx, ty;
+	unsigned char parity[32];
+	curve25519_mul(tx, p->x, zi);
+	curve25519_mul(ty, p->y, zi);
+	curve25519_contract(r, ty);
+	curve25519_contract(parity, tx);
+	r[31] ^= ((parity[0] & 1) << 7);
+}
+
 /*
 	Timing safe memory compare
 */
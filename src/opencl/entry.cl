@@ -1,3 +1,4 @@
+#ifdef HAVE_PRINTF
 inline void print_bytes(const uchar *data, size_t len) {
  	for (size_t i = 0; i < len; ++i) {
  		printf("%.2x", data[i]);
@@ -10,65 +11,669 @@ inline void print_bytes(const uchar *data, size_t len) {
  		printf("%.8x ", data[i]); }
  	printf("\n");
  }
+#endif
 
-/** * result:
- *     The 32 byte key material that is written once a matching address was found.
- *     This is all zero by default and any non-zero result indicates a match. All local
- *     threads write to the same global memory, so we can get corrupted results if
- *     multiple threads find a match. This usually does not happen for hard enough
- *     tasks but we need to double check the result in the caller code for this reason.
+/*
+ * Packs BATCH_SIZE points with a single field inversion, using Montgomery's
+ * trick: invert the product of all z coordinates once, then peel the
+ * individual inverses off it with two multiplications per point.
+ */
+static void ge25519_pack_batch(uchar pubkeys[BATCH_SIZE][32], ge25519 *points) {
+	bignum25519 products[BATCH_SIZE];
+	bignum25519 inverse, zi;
+
+	curve25519_copy(products[0], points[0].z);
+	for (size_t i = 1; i < BATCH_SIZE; i++) {
+		curve25519_mul(products[i], products[i - 1], points[i].z);
+	}
+	curve25519_recip(inverse, products[BATCH_SIZE - 1]);
+	for (size_t i = BATCH_SIZE - 1; i > 0; i--) {
+		// inverse is 1 / (z_0 * ... * z_i) here
+		curve25519_mul(zi, inverse, products[i - 1]);
+		curve25519_mul(inverse, inverse, points[i].z);
+		ge25519_pack_zi(pubkeys[i], &points[i], zi);
+	}
+	ge25519_pack_zi(pubkeys[0], &points[0], inverse);
+}
+
+/*
+ * Appends a match to the results. Matches beyond MAX_RESULTS are only counted
+ * in counters[0], so the caller can tell that some were lost.
+ */
+static void push_result(__global uint *counters, __global ulong *results, ulong offset) {
+	uint const idx = atomic_inc(&counters[0]);
+	if (idx < MAX_RESULTS) {
+		results[idx] = offset;
+	}
+}
+
+static int matches_prefix(const uchar *pubkey, __global uchar *pub_req, __global uchar *pub_mask, uchar prefix_len) {
+	for (uchar i = 0; i < prefix_len; i++) {
+		if ((pubkey[i] & pub_mask[i]) != pub_req[i]) {
+			return 0;
+		}
+	}
+	return 1;
+}
+
+/*
+ * The points of the BATCH_SIZE consecutive seeds starting at offset from
+ * key_material_base. The offset goes into the second 8 bytes, leaving the
+ * leading bits, which pick the first mnemonic words, as the host chose them.
+ */
+static void derive_seed_points(ge25519 *points, __global uchar *key_material_base, ulong offset) {
+	for (size_t b = 0; b < BATCH_SIZE; b++) {
+		uchar key_material[32];
+		for (size_t j = 0; j < 32; j++) {
+			key_material[j] = key_material_base[j];
+		}
+
+		*((ulong *) (key_material + 8)) += offset + b;
+
+		bignum256modm a;
+
+		u32 in[32] = { 0 }; // must be 128 bytes zero-filled for sha512_update to work
+		uchar hash[64];
+
+		sha512_ctx_t hasher;
+
+		sha512_init (&hasher);
+
+		to_32bytes_sha2_input(in, key_material);
+
+		sha512_update(&hasher, in, 32);
+		sha512_final(&hasher);
+
+		from_sha512_result(hash, hasher.h);
+		hash[0] &= 248;
+		hash[31] &= 127;
+		hash[31] |= 64;
+
+		expand256_modm(a, hash, 32);
+		ge25519_scalarmult_base_niels(&points[b], a);
+	}
+}
+
+/*
+ * Sets first to A + P + [8 * offset]B and step to [8]B, where A is the
+ * compressed start_point, P the compressed public_offset and B the base point.
+ */
+static void incremental_start(ge25519 *first, ge25519_pniels *step, __global uchar *start_point, __global uchar *public_offset, ulong offset) {
+	uchar scalar[32] = { 0 };
+	ulong const scaled = offset << 3;
+	for (size_t i = 0; i < 8; i++) {
+		scalar[i] = (uchar) (scaled >> (8 * i));
+	}
+
+	bignum256modm s;
+	ge25519 ALIGN(16) A;
+	expand256_modm(s, scalar, 32);
+	ge25519_scalarmult_base_niels(first, s);
+
+	uchar start[32];
+	for (size_t i = 0; i < 32; i++) {
+		start[i] = start_point[i];
+	}
+	ge25519_unpack_vartime(&A, start);
+	ge25519_add(first, first, &A);
+
+	for (size_t i = 0; i < 32; i++) {
+		start[i] = public_offset[i];
+	}
+	ge25519_unpack_vartime(&A, start);
+	ge25519_add(first, first, &A);
+
+	ge25519 ALIGN(16) step_full;
+	for (size_t i = 0; i < 32; i++) {
+		scalar[i] = 0;
+	}
+	scalar[0] = 8;
+	expand256_modm(s, scalar, 32);
+	ge25519_scalarmult_base_niels(&step_full, s);
+	ge25519_full_to_pniels(step, &step_full);
+}
+
+/*
+ * Fills points with the next BATCH_SIZE points of the walk. The first batch
+ * starts at the point already in points[0], later ones continue from the
+ * last point of the previous batch.
+ */
+static void incremental_batch(ge25519 *points, ge25519_pniels *step, uint i) {
+	ge25519_p1p1 ALIGN(16) sum;
+	if (i != 0) {
+		ge25519_pnielsadd_p1p1(&sum, &points[BATCH_SIZE - 1], step, 0);
+		ge25519_p1p1_to_full(&points[0], &sum);
+	}
+	for (size_t b = 1; b < BATCH_SIZE; b++) {
+		ge25519_pnielsadd_p1p1(&sum, &points[b - 1], step, 0);
+		ge25519_p1p1_to_full(&points[b], &sum);
+	}
+}
+
+/**
+ * Every thread derives the public keys of iterations * BATCH_SIZE consecutive
+ * seeds, packing every batch of BATCH_SIZE keys with a single field inversion.
+ *
+ * counters, results:
+ *     The number of matches and the number of threads that finished, and the
+ *     offsets of the matching seeds from key_material_base. Only the first
+ *     MAX_RESULTS offsets are stored.
  * key_material_base:
  *     The root input key material. This is 32 bytes from a cryptographically secure
- *     random number generator. The thread ID is XORed into the last 8 bytes of this.
- * max_address_value:
- *     The largest address value that is considered a match, e.g. 999999999999 when
- *     looking for 12 digit addresses.
+ *     random number generator. The offset of a seed is added to its bytes 8 to 15.
+ * pub_req, pub_mask, prefix_len:
+ *     The public key prefix to look for.
+ * iterations:
+ *     The number of batches every thread checks.
  */
-__kernel void generate_pubkey (__global unsigned long *result, __global uchar *key_material_base, __global uchar *pub_req, __global uchar *pub_mask, uchar prefix_len, __global uchar *public_offset) {
+__kernel void generate_pubkey (__global uint *counters, __global ulong *results, __global uchar *key_material_base, __global uchar *pub_req, __global uchar *pub_mask, uchar prefix_len, uint iterations) {
 	size_t const thread = get_global_id (0);
-	uchar key_material[32];
-	for (size_t i = 0; i < 32; i++) {
-		key_material[i] = key_material_base[i];
+	ulong const thread_offset = (ulong) thread * iterations * BATCH_SIZE;
+
+	ge25519 ALIGN(16) points[BATCH_SIZE];
+	uchar pubkeys[BATCH_SIZE][32];
+	for (uint i = 0; i < iterations; i++) {
+		ulong const offset = thread_offset + (ulong) i * BATCH_SIZE;
+		derive_seed_points(points, key_material_base, offset);
+		ge25519_pack_batch(pubkeys, points);
+
+		for (size_t b = 0; b < BATCH_SIZE; b++) {
+			if (matches_prefix(pubkeys[b], pub_req, pub_mask, prefix_len)) {
+				push_result(counters, results, offset + b);
+			}
+		}
 	}
+	atomic_inc(&counters[1]);
+}
 
-	*((size_t *) key_material) += thread;
+/**
+ * Searches in scalar space instead of hashing seeds. Thread t checks the
+ * points A + [8 * (t * iterations * BATCH_SIZE + i)]B for i in
+ * 0..iterations * BATCH_SIZE, where B is the base point, so neighbouring keys
+ * only cost one point addition instead of a SHA-512 and a full scalar
+ * multiplication. Stepping by 8B keeps the secret scalar clamped.
+ *
+ * Split-key searches add the public key P of the client to every point, so
+ * the worker only learns the offset from it. Otherwise P is the identity.
+ *
+ * counters, results:
+ *     The number of matches and the number of threads that finished, and the
+ *     offsets t * iterations * BATCH_SIZE + i of the matching points. Only the
+ *     first MAX_RESULTS offsets are stored, the caller turns them back into
+ *     secret scalars.
+ * start_point:
+ *     The compressed point A = [a]B of a random, clamped start scalar a.
+ * pub_req, pub_mask, prefix_len:
+ *     The public key prefix to look for.
+ * public_offset:
+ *     The compressed point P.
+ * iterations:
+ *     The number of batches of consecutive points every thread checks.
+ */
+__kernel void generate_pubkey_incremental (__global uint *counters, __global ulong *results, __global uchar *start_point, __global uchar *pub_req, __global uchar *pub_mask, uchar prefix_len, __global uchar *public_offset, uint iterations) {
+	size_t const thread = get_global_id (0);
+	ulong const offset = (ulong) thread * iterations * BATCH_SIZE;
 
-	uchar menomic_hash[32];
-	uchar *key;
+	ge25519 ALIGN(16) points[BATCH_SIZE];
+	ge25519_pniels ALIGN(16) step;
+	incremental_start(&points[0], &step, start_point, public_offset, offset);
 
-	// privkey or extended privkey
-	key = key_material;
-	bignum256modm a;
-	ge25519 ALIGN(16) A;
+	uchar pubkeys[BATCH_SIZE][32];
+	for (uint i = 0; i < iterations; i++) {
+		incremental_batch(points, &step, i);
+		ge25519_pack_batch(pubkeys, points);
 
-	u32 in[32] = { 0 }; // must be 128 bytes zero-filled for sha512_update to work
-	uchar hash[64];
+		for (size_t b = 0; b < BATCH_SIZE; b++) {
+			if (matches_prefix(pubkeys[b], pub_req, pub_mask, prefix_len)) {
+				push_result(counters, results, offset + (ulong) i * BATCH_SIZE + b);
+			}
+		}
+	}
+	atomic_inc(&counters[1]);
+}
 
+/*
+ * HMAC-SHA512 with a 32 byte key of a message of at most 111 bytes, which
+ * fits a single block.
+ */
+static void hmac_sha512_32(uchar out[64], const uchar key[32], const uchar *msg, int len) {
+	uchar pad[128];
+	u32 block[32];
+	uchar inner_hash[64];
 	sha512_ctx_t hasher;
 
-	sha512_init (&hasher);
+	for (size_t i = 0; i < 128; i++) {
+		pad[i] = (i < 32 ? key[i] : 0) ^ 0x36;
+	}
+	for (size_t i = 0; i < 32; i++) {
+		block[i] = 0;
+	}
+	to_bytes_sha2_input(block, pad, 128);
+	sha512_init(&hasher);
+	sha512_update(&hasher, block, 128);
+	for (size_t i = 0; i < 32; i++) {
+		block[i] = 0;
+	}
+	to_bytes_sha2_input(block, msg, len);
+	sha512_update(&hasher, block, len);
+	sha512_final(&hasher);
+	from_sha512_result(inner_hash, hasher.h);
+
+	for (size_t i = 0; i < 128; i++) {
+		pad[i] ^= 0x36 ^ 0x5c;
+	}
+	for (size_t i = 0; i < 32; i++) {
+		block[i] = 0;
+	}
+	to_bytes_sha2_input(block, pad, 128);
+	sha512_init(&hasher);
+	sha512_update(&hasher, block, 128);
+	for (size_t i = 0; i < 32; i++) {
+		block[i] = 0;
+	}
+	to_bytes_sha2_input(block, inner_hash, 64);
+	sha512_update(&hasher, block, 64);
+	sha512_final(&hasher);
+	from_sha512_result(out, hasher.h);
+}
+
+/*
+ * The account chain of an HD launch: the compressed public key A and chain
+ * code c of m/44'/283'/account'/0, the first child index and the number of
+ * bits BIP32-Ed25519 truncates.
+ */
+typedef struct {
+	ge25519 ALIGN(16) point;
+	uchar pubkey[32];
+	uchar chain_code[32];
+	uint first_index;
+	uchar truncated_bits;
+} hd_chain;
+
+static void load_hd_chain(hd_chain *chain, __global uchar *root) {
+	for (size_t i = 0; i < 32; i++) {
+		chain->pubkey[i] = root[i];
+		chain->chain_code[i] = root[32 + i];
+	}
+	chain->first_index = root[64] | (root[65] << 8) | (root[66] << 16) | ((uint) root[67] << 24);
+	chain->truncated_bits = root[68];
+	ge25519_unpack_vartime(&chain->point, chain->pubkey);
+}
+
+/*
+ * The public key of the non-hardened child index of the chain, which is
+ * A + [8 * trunc(zL)]B with z = HMAC-SHA512(c, 0x02 || A || index).
+ */
+static void hd_child_point(ge25519 *point, const hd_chain *chain, uint index) {
+	uchar msg[37];
+	msg[0] = 0x02;
+	for (size_t i = 0; i < 32; i++) {
+		msg[1 + i] = chain->pubkey[i];
+	}
+	for (size_t i = 0; i < 4; i++) {
+		msg[33 + i] = (uchar) (index >> (8 * i));
+	}
+	uchar z[64];
+	hmac_sha512_32(z, chain->chain_code, msg, 37);
+
+	uint remaining = chain->truncated_bits;
+	for (int i = 31; i >= 0 && remaining > 0; i--) {
+		if (remaining >= 8) {
+			z[i] = 0;
+			remaining -= 8;
+		} else {
+			z[i] &= 0xff >> remaining;
+			remaining = 0;
+		}
+	}
+	uchar scalar[32];
+	uchar carry = 0;
+	for (size_t i = 0; i < 32; i++) {
+		scalar[i] = (uchar) (z[i] << 3) | carry;
+		carry = z[i] >> 5;
+	}
+
+	bignum256modm s;
+	expand256_modm(s, scalar, 32);
+	ge25519_scalarmult_base_niels(point, s);
+	ge25519_add(point, point, &chain->point);
+}
+
+/**
+ * Searches the children m/44'/283'/account'/0/index of an ARC-0052 HD wallet.
+ * Every child costs two HMAC-SHA512 and a scalar multiplication, like a seed.
+ *
+ * counters, results:
+ *     The number of matches and the number of threads that finished, and the
+ *     offsets of the matching children from the first index. Only the first
+ *     MAX_RESULTS offsets are stored.
+ * chain:
+ *     The account chain, see hd_chain.
+ * pub_req, pub_mask, prefix_len:
+ *     The public key prefix to look for.
+ * iterations:
+ *     The number of batches every thread checks.
+ */
+__kernel void generate_pubkey_hd (__global uint *counters, __global ulong *results, __global uchar *chain, __global uchar *pub_req, __global uchar *pub_mask, uchar prefix_len, uint iterations) {
+	size_t const thread = get_global_id (0);
+	ulong const thread_offset = (ulong) thread * iterations * BATCH_SIZE;
+
+	hd_chain account;
+	load_hd_chain(&account, chain);
+
+	ge25519 ALIGN(16) points[BATCH_SIZE];
+	uchar pubkeys[BATCH_SIZE][32];
+	for (uint i = 0; i < iterations; i++) {
+		ulong const offset = thread_offset + (ulong) i * BATCH_SIZE;
+		for (size_t b = 0; b < BATCH_SIZE; b++) {
+			hd_child_point(&points[b], &account, account.first_index + (uint) (offset + b));
+		}
+		ge25519_pack_batch(pubkeys, points);
 
-	to_32bytes_sha2_input(in, key);
+		for (size_t b = 0; b < BATCH_SIZE; b++) {
+			if (matches_prefix(pubkeys[b], pub_req, pub_mask, prefix_len)) {
+				push_result(counters, results, offset + b);
+			}
+		}
+	}
+	atomic_inc(&counters[1]);
+}
 
-	sha512_update(&hasher, in, 32);
+/*
+ * SHA-512/256 of the message with the slot_len bytes at slot_pos replaced by
+ * slot, which is how the addresses of logic signatures and multisig accounts
+ * are hashed.
+ */
+static void sha512_256_with_slot(uchar out[32], __global uchar *message, uint message_len, uint slot_pos, const uchar *slot, uint slot_len) {
+	sha512_ctx_t hasher;
+	sha512_256_init(&hasher);
+	uchar chunk[128];
+	u32 block[32];
+	for (uint start = 0; start < message_len; start += 128) {
+		uint const len = message_len - start < 128 ? message_len - start : 128;
+		for (uint i = 0; i < len; i++) {
+			// wraps around for the bytes before the slot
+			uint const slot_idx = start + i - slot_pos;
+			chunk[i] = slot_idx < slot_len ? slot[slot_idx] : message[start + i];
+		}
+		for (size_t i = 0; i < 32; i++) {
+			block[i] = 0;
+		}
+		to_bytes_sha2_input(block, chunk, len);
+		sha512_update(&hasher, block, len);
+	}
 	sha512_final(&hasher);
+	from_sha512_256_result(out, hasher.h);
+}
+
+/*
+ * SHA-512/256 of the message with the nonce at offset from nonce_base in
+ * place of its nonce_len bytes at nonce_pos. The nonce is the start of
+ * nonce_base with the offset added to its first 8 bytes. This is the contract
+ * account address for message = "Program" || program, and the transaction ID
+ * for message = "TX" || transaction.
+ */
+static void nonce_hash(uchar out[32], __global uchar *nonce_base, __global uchar *message, uint message_len, uint nonce_pos, uchar nonce_len, ulong offset) {
+	uchar nonce[32];
+	ulong counter = 0;
+	for (size_t i = 0; i < 8; i++) {
+		counter |= (ulong) nonce_base[i] << (8 * i);
+	}
+	counter += offset;
+	for (size_t i = 0; i < 32; i++) {
+		nonce[i] = i < 8 ? (uchar) (counter >> (8 * i)) : nonce_base[i];
+	}
+	sha512_256_with_slot(out, message, message_len, nonce_pos, nonce, nonce_len);
+}
 
-	from_sha512_result(hash, hasher.h);
-	hash[0] &= 248;
-	hash[31] &= 127;
-	hash[31] |= 64;
+/**
+ * Searches nonces of a logic signature for a contract account address with
+ * the prefix. Every nonce costs a SHA-512/256 of the program, so threads
+ * check iterations * BATCH_SIZE of them to keep launches as large as in the
+ * other modes.
+ *
+ * counters, results:
+ *     The number of matches and the number of threads that finished, and the
+ *     offsets of the matching nonces from nonce_base. Only the first
+ *     MAX_RESULTS offsets are stored.
+ * nonce_base:
+ *     32 random bytes, the first nonce_len of which are the nonce at offset 0.
+ * message, message_len, nonce_pos, nonce_len:
+ *     "Program" followed by the program, and where its placeholder is.
+ * pub_req, pub_mask, prefix_len:
+ *     The address prefix to look for.
+ * iterations:
+ *     The number of batches every thread checks.
+ */
+__kernel void generate_logicsig (__global uint *counters, __global ulong *results, __global uchar *nonce_base, __global uchar *message, uint message_len, uint nonce_pos, uchar nonce_len, __global uchar *pub_req, __global uchar *pub_mask, uchar prefix_len, uint iterations) {
+	size_t const thread = get_global_id (0);
+	ulong const keys = (ulong) iterations * BATCH_SIZE;
+	ulong const thread_offset = (ulong) thread * keys;
 
-	expand256_modm(a, hash, 32);
-	ge25519_scalarmult_base_niels(&A, a);
+	uchar address[32];
+	for (ulong i = 0; i < keys; i++) {
+		nonce_hash(address, nonce_base, message, message_len, nonce_pos, nonce_len, thread_offset + i);
+		if (matches_prefix(address, pub_req, pub_mask, prefix_len)) {
+			push_result(counters, results, thread_offset + i);
+		}
+	}
+	atomic_inc(&counters[1]);
+}
 
-	uchar pubkey[32];
-	ge25519_pack(pubkey, &A);
+/*
+ * The transaction ID with the note nonce at offset, or the ID of its group
+ * when group_len is not 0. The group message "TG" || group follows the
+ * transaction message, with the ID of the transaction at group_pos.
+ */
+static void txid_hash(uchar id[32], __global uchar *nonce_base, __global uchar *message, uint message_len, uint nonce_pos, uchar nonce_len, uint group_len, uint group_pos, ulong offset) {
+	nonce_hash(id, nonce_base, message, message_len, nonce_pos, nonce_len, offset);
+	if (group_len != 0) {
+		uchar txid[32];
+		for (size_t i = 0; i < 32; i++) {
+			txid[i] = id[i];
+		}
+		sha512_256_with_slot(id, message + message_len, group_len, group_pos, txid, 32);
+	}
+}
 
-	for (uchar i = 0; i < prefix_len; i++) {
-		if ((pubkey[i] & pub_mask[i]) != pub_req[i]) {
-			return;
+/**
+ * Searches nonces in the note of a transaction for a transaction or group ID
+ * with the prefix, like generate_logicsig.
+ *
+ * counters, results:
+ *     The number of matches and the number of threads that finished, and the
+ *     offsets of the matching nonces from nonce_base. Only the first
+ *     MAX_RESULTS offsets are stored.
+ * nonce_base:
+ *     32 random bytes, the first nonce_len of which are the nonce at offset 0.
+ * message, message_len, nonce_pos, nonce_len:
+ *     "TX" followed by the transaction, and where the nonce in its note is.
+ * group_len, group_pos:
+ *     The length of the group message following the transaction message, 0
+ *     without a group, and where the ID of the transaction is in it.
+ * pub_req, pub_mask, prefix_len:
+ *     The ID prefix to look for.
+ * iterations:
+ *     The number of batches every thread checks.
+ */
+__kernel void generate_txid (__global uint *counters, __global ulong *results, __global uchar *nonce_base, __global uchar *message, uint message_len, uint nonce_pos, uchar nonce_len, uint group_len, uint group_pos, __global uchar *pub_req, __global uchar *pub_mask, uchar prefix_len, uint iterations) {
+	size_t const thread = get_global_id (0);
+	ulong const keys = (ulong) iterations * BATCH_SIZE;
+	ulong const thread_offset = (ulong) thread * keys;
+
+	uchar id[32];
+	for (ulong i = 0; i < keys; i++) {
+		txid_hash(id, nonce_base, message, message_len, nonce_pos, nonce_len, group_len, group_pos, thread_offset + i);
+		if (matches_prefix(id, pub_req, pub_mask, prefix_len)) {
+			push_result(counters, results, thread_offset + i);
+		}
+	}
+	atomic_inc(&counters[1]);
+}
+
+/**
+ * Searches seeds of a new multisig member for a multisig address with the
+ * prefix. Every seed costs a scalar multiplication like in generate_pubkey,
+ * plus a SHA-512/256 of the multisig preimage.
+ *
+ * counters, results:
+ *     The number of matches and the number of threads that finished, and the
+ *     offsets of the matching seeds from key_material_base. Only the first
+ *     MAX_RESULTS offsets are stored.
+ * key_material_base:
+ *     32 random bytes, see generate_pubkey.
+ * message, message_len, member_pos:
+ *     "MultisigAddr" || version || threshold || public keys, and where the
+ *     public key of the new member goes.
+ * pub_req, pub_mask, prefix_len:
+ *     The address prefix to look for.
+ * iterations:
+ *     The number of batches every thread checks.
+ */
+__kernel void generate_multisig (__global uint *counters, __global ulong *results, __global uchar *key_material_base, __global uchar *message, uint message_len, uint member_pos, __global uchar *pub_req, __global uchar *pub_mask, uchar prefix_len, uint iterations) {
+	size_t const thread = get_global_id (0);
+	ulong const thread_offset = (ulong) thread * iterations * BATCH_SIZE;
+
+	ge25519 ALIGN(16) points[BATCH_SIZE];
+	uchar pubkeys[BATCH_SIZE][32];
+	uchar address[32];
+	for (uint i = 0; i < iterations; i++) {
+		ulong const offset = thread_offset + (ulong) i * BATCH_SIZE;
+		derive_seed_points(points, key_material_base, offset);
+		ge25519_pack_batch(pubkeys, points);
+		for (size_t b = 0; b < BATCH_SIZE; b++) {
+			sha512_256_with_slot(address, message, message_len, member_pos, pubkeys[b], 32);
+			if (matches_prefix(address, pub_req, pub_mask, prefix_len)) {
+				push_result(counters, results, offset + b);
+			}
+		}
+	}
+	atomic_inc(&counters[1]);
+}
+
+static void store_pubkeys(__global uchar *out, uchar pubkeys[BATCH_SIZE][32], ulong offset) {
+	for (size_t b = 0; b < BATCH_SIZE; b++) {
+		for (size_t j = 0; j < 32; j++) {
+			out[(offset + b) * 32 + j] = pubkeys[b][j];
+		}
+	}
+}
+
+/**
+ * Known-answer self-test of generate_pubkey: writes the public keys of all
+ * the seeds a launch checks to pubkeys, 32 bytes per offset.
+ */
+__kernel void self_test_pubkeys (__global uchar *pubkeys, __global uchar *key_material_base, uint iterations) {
+	size_t const thread = get_global_id (0);
+	ulong const thread_offset = (ulong) thread * iterations * BATCH_SIZE;
+
+	ge25519 ALIGN(16) points[BATCH_SIZE];
+	uchar packed[BATCH_SIZE][32];
+	for (uint i = 0; i < iterations; i++) {
+		ulong const offset = thread_offset + (ulong) i * BATCH_SIZE;
+		derive_seed_points(points, key_material_base, offset);
+		ge25519_pack_batch(packed, points);
+		store_pubkeys(pubkeys, packed, offset);
+	}
+}
+
+/**
+ * Known-answer self-test of generate_pubkey_incremental: writes all the
+ * points a launch checks to pubkeys, 32 bytes per offset.
+ */
+__kernel void self_test_pubkeys_incremental (__global uchar *pubkeys, __global uchar *start_point, uint iterations, __global uchar *public_offset) {
+	size_t const thread = get_global_id (0);
+	ulong const offset = (ulong) thread * iterations * BATCH_SIZE;
+
+	ge25519 ALIGN(16) points[BATCH_SIZE];
+	ge25519_pniels ALIGN(16) step;
+	incremental_start(&points[0], &step, start_point, public_offset, offset);
+
+	uchar packed[BATCH_SIZE][32];
+	for (uint i = 0; i < iterations; i++) {
+		incremental_batch(points, &step, i);
+		ge25519_pack_batch(packed, points);
+		store_pubkeys(pubkeys, packed, offset + (ulong) i * BATCH_SIZE);
+	}
+}
+
+/**
+ * Known-answer self-test of generate_pubkey_hd: writes the public keys of all
+ * the children a launch checks to pubkeys, 32 bytes per offset.
+ */
+__kernel void self_test_pubkeys_hd (__global uchar *pubkeys, __global uchar *chain, uint iterations) {
+	size_t const thread = get_global_id (0);
+	ulong const thread_offset = (ulong) thread * iterations * BATCH_SIZE;
+
+	hd_chain account;
+	load_hd_chain(&account, chain);
+
+	ge25519 ALIGN(16) points[BATCH_SIZE];
+	uchar packed[BATCH_SIZE][32];
+	for (uint i = 0; i < iterations; i++) {
+		ulong const offset = thread_offset + (ulong) i * BATCH_SIZE;
+		for (size_t b = 0; b < BATCH_SIZE; b++) {
+			hd_child_point(&points[b], &account, account.first_index + (uint) (offset + b));
+		}
+		ge25519_pack_batch(packed, points);
+		store_pubkeys(pubkeys, packed, offset);
+	}
+}
+
+/**
+ * Known-answer self-test of generate_logicsig: writes the addresses of all
+ * the nonces a launch checks to addresses, 32 bytes per offset.
+ */
+__kernel void self_test_logicsig (__global uchar *addresses, __global uchar *nonce_base, uint iterations, __global uchar *message, uint message_len, uint nonce_pos, uchar nonce_len) {
+	size_t const thread = get_global_id (0);
+	ulong const keys = (ulong) iterations * BATCH_SIZE;
+	ulong const thread_offset = (ulong) thread * keys;
+
+	uchar address[32];
+	for (ulong i = 0; i < keys; i++) {
+		nonce_hash(address, nonce_base, message, message_len, nonce_pos, nonce_len, thread_offset + i);
+		for (size_t j = 0; j < 32; j++) {
+			addresses[(thread_offset + i) * 32 + j] = address[j];
 		}
 	}
+}
 
-	*result = thread;
+/**
+ * Known-answer self-test of generate_multisig: writes the multisig addresses
+ * of all the seeds a launch checks to addresses, 32 bytes per offset.
+ */
+__kernel void self_test_multisig (__global uchar *addresses, __global uchar *key_material_base, uint iterations, __global uchar *message, uint message_len, uint member_pos) {
+	size_t const thread = get_global_id (0);
+	ulong const thread_offset = (ulong) thread * iterations * BATCH_SIZE;
+
+	ge25519 ALIGN(16) points[BATCH_SIZE];
+	uchar pubkeys[BATCH_SIZE][32];
+	uchar packed[BATCH_SIZE][32];
+	for (uint i = 0; i < iterations; i++) {
+		ulong const offset = thread_offset + (ulong) i * BATCH_SIZE;
+		derive_seed_points(points, key_material_base, offset);
+		ge25519_pack_batch(pubkeys, points);
+		for (size_t b = 0; b < BATCH_SIZE; b++) {
+			sha512_256_with_slot(packed[b], message, message_len, member_pos, pubkeys[b], 32);
+		}
+		store_pubkeys(addresses, packed, offset);
+	}
+}
+
+/**
+ * Known-answer self-test of generate_txid: writes the IDs of all the nonces a
+ * launch checks to ids, 32 bytes per offset.
+ */
+__kernel void self_test_txid (__global uchar *ids, __global uchar *nonce_base, uint iterations, __global uchar *message, uint message_len, uint nonce_pos, uchar nonce_len, uint group_len, uint group_pos) {
+	size_t const thread = get_global_id (0);
+	ulong const keys = (ulong) iterations * BATCH_SIZE;
+	ulong const thread_offset = (ulong) thread * keys;
+
+	uchar id[32];
+	for (ulong i = 0; i < keys; i++) {
+		txid_hash(id, nonce_base, message, message_len, nonce_pos, nonce_len, group_len, group_pos, thread_offset + i);
+		for (size_t j = 0; j < 32; j++) {
+			ids[(thread_offset + i) * 32 + j] = id[j];
+		}
+	}
 }
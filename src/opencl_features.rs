@@ -0,0 +1,195 @@
+use std::fmt;
+
+use ocl;
+use ocl::enums::DeviceInfo;
+use ocl::Device;
+use ocl::Result;
+
+/// `CL_DEVICE_OPENCL_C_FEATURES`, which ocl has no enum variant for. OpenCL
+/// 3.0 made the generic address space optional and reports it here.
+const CL_DEVICE_OPENCL_C_FEATURES: u32 = 0x106F;
+
+/// The size of a `cl_name_version`: a version followed by a 64 byte name
+const NAME_VERSION_SIZE: usize = 4 + 64;
+
+/// What the OpenCL C compiler of a device supports. Decides the defines and
+/// options the kernels are built with.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DeviceFeatures {
+    /// The OpenCL C version of the device, e.g. (1, 2)
+    pub c_version: (u32, u32),
+    /// Helpers take `__generic` pointers instead of `__private` ones
+    pub generic_address_space: bool,
+    /// `HAVE_PRINTF` is set, enabling the debug print helpers
+    pub printf: bool,
+}
+
+/// The major and minor version of an `OpenCL C <major>.<minor> ...` string
+fn parse_c_version(version: &str) -> Option<(u32, u32)> {
+    let number = version.strip_prefix("OpenCL C ")?.split_whitespace().next()?;
+    let mut parts = number.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+/// The names of an array of `cl_name_version`
+fn parse_name_versions(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(NAME_VERSION_SIZE)
+        .filter(|chunk| chunk.len() == NAME_VERSION_SIZE)
+        .map(|chunk| {
+            let name = &chunk[4..];
+            let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            String::from_utf8_lossy(&name[..len]).into_owned()
+        })
+        .collect()
+}
+
+impl DeviceFeatures {
+    fn from_info(c_version: (u32, u32), extensions: &str, c_features: &[String]) -> DeviceFeatures {
+        let has_extension = |name: &str| extensions.split_whitespace().any(|ext| ext == name);
+        let generic_address_space = match c_version.0 {
+            0 | 1 => false,
+            2 => true,
+            _ => c_features
+                .iter()
+                .any(|feature| feature == "__opencl_c_generic_address_space"),
+        };
+        DeviceFeatures {
+            c_version,
+            generic_address_space,
+            printf: c_version >= (1, 2)
+                || has_extension("cl_intel_printf")
+                || has_extension("cl_amd_printf"),
+        }
+    }
+
+    /// Asks the driver what the device supports
+    pub fn probe(device: Device) -> Result<DeviceFeatures> {
+        let version = device.info(DeviceInfo::OpenclCVersion)?.to_string();
+        let c_version = parse_c_version(&version)
+            .ok_or_else(|| format!("Unknown OpenCL C version: {}", version))?;
+        let extensions = device.info(DeviceInfo::Extensions)?.to_string();
+        // drivers before OpenCL 3.0 don't know the query
+        let c_features = if c_version.0 >= 3 {
+            ocl::core::get_device_info_raw(device, CL_DEVICE_OPENCL_C_FEATURES)
+                .map(|bytes| parse_name_versions(&bytes))
+                .unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        Ok(DeviceFeatures::from_info(c_version, &extensions, &c_features))
+    }
+
+    /// Overrides probed features for drivers that misreport them. `spec` is a
+    /// comma separated list of `generic` and `printf`, each
+    /// optionally prefixed with `no-` to disable it.
+    pub fn apply_overrides(&mut self, spec: &str) -> std::result::Result<(), String> {
+        for item in spec.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+            let (name, enabled) = match item.strip_prefix("no-") {
+                Some(name) => (name, false),
+                None => (item, true),
+            };
+            match name {
+                "generic" => self.generic_address_space = enabled,
+                "printf" => self.printf = enabled,
+                _ => return Err(format!("Unknown OpenCL feature: {}", name)),
+            }
+        }
+        Ok(())
+    }
+
+    /// The source lines defining the macros the kernels check
+    pub fn defines(&self) -> String {
+        let qualifier = if self.generic_address_space {
+            "__generic"
+        } else {
+            "__private"
+        };
+        let mut defines = format!("#define NAMESPACE_QUALIFIER {}\n", qualifier);
+        if self.printf {
+            defines.push_str("#define HAVE_PRINTF\n");
+        }
+        defines
+    }
+
+    /// The compiler options the defines need. Compilers default to OpenCL C
+    /// 1.2, which has no generic address space.
+    pub fn compiler_options(&self) -> Option<String> {
+        match self.c_version {
+            (major, minor) if self.generic_address_space && major >= 2 => {
+                Some(format!("-cl-std=CL{}.{}", major, minor))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DeviceFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (major, minor) = self.c_version;
+        write!(f, "OpenCL C {}.{}", major, minor)?;
+        let mut names = Vec::new();
+        if self.generic_address_space {
+            names.push("generic address space");
+        }
+        if self.printf {
+            names.push("printf");
+        }
+        if !names.is_empty() {
+            write!(f, " with {}", names.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name_version(name: &str) -> Vec<u8> {
+        let mut bytes = vec![0u8; NAME_VERSION_SIZE];
+        bytes[4..4 + name.len()].copy_from_slice(name.as_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_probe_parsing() {
+        assert_eq!(parse_c_version("OpenCL C 1.2 "), Some((1, 2)));
+        assert_eq!(parse_c_version("OpenCL C 3.0 (build 5)"), Some((3, 0)));
+        assert_eq!(parse_c_version("OpenCL 1.2"), None);
+
+        let mut bytes = name_version("__opencl_c_fp64");
+        bytes.extend(name_version("__opencl_c_generic_address_space"));
+        let c_features = parse_name_versions(&bytes);
+        assert_eq!(
+            c_features,
+            ["__opencl_c_fp64", "__opencl_c_generic_address_space"]
+        );
+
+        let old = DeviceFeatures::from_info((1, 1), "cl_khr_fp64 cl_amd_printf", &[]);
+        assert!(!old.generic_address_space && old.printf);
+        let cl2 = DeviceFeatures::from_info((2, 0), "", &[]);
+        assert!(cl2.generic_address_space && cl2.printf);
+        assert_eq!(cl2.compiler_options(), Some("-cl-std=CL2.0".to_string()));
+        let cl3 = DeviceFeatures::from_info((3, 0), "", &[]);
+        assert!(!cl3.generic_address_space);
+        assert_eq!(cl3.compiler_options(), None);
+        let cl3 = DeviceFeatures::from_info((3, 0), "", &c_features);
+        assert!(cl3.generic_address_space);
+    }
+
+    #[test]
+    fn test_overrides() {
+        let mut features = DeviceFeatures::from_info((2, 0), "", &[]);
+        features.apply_overrides("no-generic, printf").unwrap();
+        assert!(!features.generic_address_space && features.printf);
+        assert!(features
+            .defines()
+            .starts_with("#define NAMESPACE_QUALIFIER __private\n"));
+        assert!(features.defines().contains("#define HAVE_PRINTF\n"));
+        assert_eq!(features.compiler_options(), None);
+        assert!(features.apply_overrides("turbo").is_err());
+    }
+}
@@ -0,0 +1,83 @@
+use ring::signature::{Ed25519KeyPair, UnparsedPublicKey, ED25519};
+
+use derivation;
+
+/// What Algorand's SDKs put before arbitrary bytes they sign, so that the
+/// signatures can't pass for signatures of transactions. With it the proofs
+/// can also be checked with `verifyBytes` of the SDKs.
+const PREFIX: &[u8] = b"MX";
+
+fn message(challenge: &[u8]) -> Vec<u8> {
+    [PREFIX, challenge].concat()
+}
+
+/// Signs `challenge` with a seed, proving that we hold the key of its address
+pub fn sign_seed(seed: &[u8; 32], challenge: &[u8]) -> [u8; 64] {
+    let key_pair = Ed25519KeyPair::from_seed_unchecked(seed).unwrap();
+    let mut signature = [0u8; 64];
+    signature.copy_from_slice(key_pair.sign(&message(challenge)).as_ref());
+    signature
+}
+
+/// Like `sign_seed`, with an expanded secret key
+pub fn sign_expanded(expanded: &[u8; 64], challenge: &[u8]) -> [u8; 64] {
+    derivation::ed25519_expanded_sign(expanded, &message(challenge))
+}
+
+/// Parses a signature given in base64, like the SDKs print them, or in hex
+pub fn parse_signature(signature: &str) -> Result<[u8; 64], String> {
+    let bytes = if signature.len() == 128 {
+        hex::decode(signature).map_err(|err| format!("Invalid hex signature: {}", err))?
+    } else {
+        data_encoding::BASE64
+            .decode(signature.as_bytes())
+            .map_err(|err| format!("Invalid base64 signature: {}", err))?
+    };
+    if bytes.len() != 64 {
+        return Err(format!("A signature has 64 bytes, not {}", bytes.len()));
+    }
+    let mut parsed = [0u8; 64];
+    parsed.copy_from_slice(&bytes);
+    Ok(parsed)
+}
+
+/// Whether the key of `address` signed `challenge`
+pub fn verify(address: &str, challenge: &[u8], signature: &[u8; 64]) -> Result<bool, String> {
+    let pubkey = derivation::address_to_pubkey(address)?;
+    Ok(UnparsedPublicKey::new(&ED25519, &pubkey[..])
+        .verify(&message(challenge), &signature[..])
+        .is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sha2::{Digest, Sha512};
+
+    #[test]
+    fn test_proof() {
+        let seed = [0x42u8; 32];
+        let address = derivation::pubkey_to_address(&derivation::ed25519_privkey_to_pubkey(&seed));
+        let signature = sign_seed(&seed, b"challenge");
+        assert_eq!(verify(&address, b"challenge", &signature), Ok(true));
+        assert_eq!(verify(&address, b"challenge!", &signature), Ok(false));
+        // signing the bare challenge proves nothing
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&seed).unwrap();
+        let mut bare = [0u8; 64];
+        bare.copy_from_slice(key_pair.sign(b"challenge").as_ref());
+        assert_eq!(verify(&address, b"challenge", &bare), Ok(false));
+
+        let mut expanded = [0u8; 64];
+        expanded.copy_from_slice(&Sha512::digest(seed));
+        expanded[0] &= 248;
+        expanded[31] &= 127;
+        expanded[31] |= 64;
+        assert_eq!(sign_expanded(&expanded, b"challenge"), signature);
+
+        let encoded = data_encoding::BASE64.encode(&signature);
+        assert_eq!(parse_signature(&encoded), Ok(signature));
+        assert_eq!(parse_signature(&hex::encode(&signature[..])), Ok(signature));
+        assert!(parse_signature(&encoded[4..]).is_err());
+        assert!(verify(&address[1..], b"challenge", &signature).is_err());
+    }
+}
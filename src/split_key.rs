@@ -0,0 +1,131 @@
+use curve25519_dalek::constants::ED25519_BASEPOINT_POINT;
+use curve25519_dalek::edwards::{CompressedEdwardsY, EdwardsPoint};
+use curve25519_dalek::scalar::Scalar;
+use sha2::{Digest, Sha512};
+
+/// The compressed identity point, which is the public offset of searches
+/// that are not split
+pub const IDENTITY: [u8; 32] = [
+    1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+
+/// Checks that `pubkey` is a point workers can search from. Small order
+/// points would let the offset alone reveal the key of the address.
+pub fn parse_point(pubkey: &[u8; 32]) -> Result<EdwardsPoint, String> {
+    let point = CompressedEdwardsY(*pubkey)
+        .decompress()
+        .ok_or("The split-key request is not a valid public key")?;
+    if point.is_small_order() {
+        return Err("The split-key request is a point of small order".to_string());
+    }
+    Ok(point)
+}
+
+/// The public key P of the client's secret seed, which goes to the workers.
+/// They look for an offset k such that P + [k]B matches the pattern, without
+/// learning the secret of the address, which only `combine` can compute.
+pub fn request(secret: &[u8; 32]) -> [u8; 32] {
+    let (scalar, _) = expand_secret(secret);
+    EdwardsPoint::mul_base(&scalar).compress().to_bytes()
+}
+
+fn expand_secret(secret: &[u8; 32]) -> (Scalar, [u8; 32]) {
+    let hash = Sha512::digest(secret);
+    let mut scalar = [0u8; 32];
+    scalar.copy_from_slice(&hash[..32]);
+    let mut prefix = [0u8; 32];
+    prefix.copy_from_slice(&hash[32..]);
+    (Scalar::from_bytes_mod_order(clamp(scalar)), prefix)
+}
+
+fn clamp(mut scalar: [u8; 32]) -> [u8; 32] {
+    scalar[0] &= 248;
+    scalar[31] &= 127;
+    scalar[31] |= 64;
+    scalar
+}
+
+/// The public key P + [offset]B a worker checks
+pub fn offset_pubkey(point: &EdwardsPoint, offset: &[u8; 32]) -> [u8; 32] {
+    (point + EdwardsPoint::mul_base(&Scalar::from_bytes_mod_order(*offset)))
+        .compress()
+        .to_bytes()
+}
+
+/// The public keys of `count` consecutive offsets start + 8i of an
+/// incremental search, see `derivation::incremental_scalar`
+pub fn offset_pubkeys(point: &EdwardsPoint, start: &[u8; 32], count: usize) -> Vec<[u8; 32]> {
+    let step = ED25519_BASEPOINT_POINT.mul_by_cofactor();
+    let mut current = point + EdwardsPoint::mul_base(&Scalar::from_bytes_mod_order(*start));
+    let points: Vec<EdwardsPoint> = (0..count)
+        .map(|_| {
+            let point = current;
+            current += step;
+            point
+        })
+        .collect();
+    EdwardsPoint::compress_batch_alloc(&points)
+        .into_iter()
+        .map(|point| point.to_bytes())
+        .collect()
+}
+
+/// The expanded secret key of the address a worker found with `offset`: the
+/// client's secret scalar plus the offset, followed by a nonce prefix of its
+/// own. The workers know the offsets, so keys sharing the client's prefix
+/// would reveal its secret scalar by signing the same message.
+pub fn combine(secret: &[u8; 32], offset: &[u8; 32]) -> [u8; 64] {
+    let (scalar, prefix) = expand_secret(secret);
+    let combined = scalar + Scalar::from_bytes_mod_order(*offset);
+    let mut expanded = [0u8; 64];
+    expanded[..32].copy_from_slice(combined.as_bytes());
+    expanded[32..].copy_from_slice(
+        &Sha512::new()
+            .chain_update(prefix)
+            .chain_update(offset)
+            .finalize()[..32],
+    );
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use derivation;
+
+    #[test]
+    fn test_combine() {
+        let secret = [7u8; 32];
+        let pubkey = request(&secret);
+        assert_eq!(pubkey, derivation::ed25519_privkey_to_pubkey(&secret));
+        let point = parse_point(&pubkey).unwrap();
+        assert!(parse_point(&IDENTITY).is_err());
+
+        let mut start = [0x42u8; 32];
+        derivation::clamp_incremental_start(&mut start);
+        let pubkeys = offset_pubkeys(&point, &start, 3);
+        let offset = derivation::incremental_scalar(&start, 2);
+        assert_eq!(pubkeys[2], offset_pubkey(&point, &offset));
+
+        let expanded = combine(&secret, &offset);
+        let mut scalar = [0u8; 32];
+        scalar.copy_from_slice(&expanded[..32]);
+        assert_eq!(derivation::ed25519_scalar_to_pubkey(&scalar), pubkeys[2]);
+        let signature = derivation::ed25519_expanded_sign(&expanded, b"split");
+        assert!(
+            ring::signature::UnparsedPublicKey::new(&ring::signature::ED25519, &pubkeys[2][..])
+                .verify(b"split", &signature)
+                .is_ok()
+        );
+
+        // the nonces of other offsets and of the client's own key differ
+        let other = combine(&secret, &derivation::incremental_scalar(&start, 1));
+        assert_ne!(other[32..], expanded[32..]);
+        let other_signature = derivation::ed25519_expanded_sign(&other, b"split");
+        assert_ne!(other_signature[..32], signature[..32]);
+        let own_signature = ring::signature::Ed25519KeyPair::from_seed_unchecked(&secret)
+            .unwrap()
+            .sign(b"split");
+        assert_ne!(own_signature.as_ref()[..32], signature[..32]);
+    }
+}
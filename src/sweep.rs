@@ -0,0 +1,325 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use sha2::{Digest, Sha512_256};
+
+/// A search whose key bases are derived from a master seed and a batch
+/// counter instead of being random, so which keys were checked can be
+/// audited, resumed and split between workers with disjoint counter ranges.
+pub struct Sweep {
+    master: [u8; 32],
+    /// The search mode, which decides what the key bases are used for
+    mode: &'static str,
+    /// Fingerprint of what else turns the key bases into keys, like the
+    /// mnemonic prefix or the template of the mode
+    keyspace: String,
+    start: u64,
+    end: u64,
+    checkpoint: Option<(PathBuf, usize)>,
+    state: Mutex<State>,
+}
+
+struct State {
+    next: u64,
+    /// Counters handed out whose batches have not finished yet
+    pending: BTreeSet<u64>,
+    /// Counters of batches lost to device errors, which are handed out again
+    retry: Vec<u64>,
+    /// The fewest keys a finished batch checked
+    min_keys: Option<usize>,
+    /// Batches finished since the last checkpoint
+    unsaved: usize,
+}
+
+/// What a checkpoint records: every batch of `start..done` finished,
+/// checking at least `min_keys` keys
+#[derive(PartialEq, Eq, Debug)]
+struct Checkpoint {
+    master: String,
+    mode: String,
+    keyspace: String,
+    start: u64,
+    done: u64,
+    end: u64,
+    min_keys: Option<usize>,
+}
+
+impl Sweep {
+    /// Sweeps the counters `start..end`, saving a checkpoint to `checkpoint`
+    /// every so many finished batches. `keyspace` are the parts of the search
+    /// besides the mode that decide which keys a key base stands for.
+    pub fn new(
+        master: [u8; 32],
+        mode: &'static str,
+        keyspace: &[&[u8]],
+        start: u64,
+        end: u64,
+        checkpoint: Option<(PathBuf, usize)>,
+    ) -> Sweep {
+        Sweep {
+            master,
+            mode,
+            keyspace: keyspace_fingerprint(keyspace),
+            start,
+            end,
+            checkpoint,
+            state: Mutex::new(State {
+                next: start,
+                pending: BTreeSet::new(),
+                retry: Vec::new(),
+                min_keys: None,
+                unsaved: 0,
+            }),
+        }
+    }
+
+    /// Continues the sweep saved to `path`, which must have been made with
+    /// the same master seed, search mode and keyspace
+    pub fn resume(
+        master: [u8; 32],
+        mode: &'static str,
+        keyspace: &[&[u8]],
+        path: PathBuf,
+        interval: usize,
+    ) -> Result<Sweep, String> {
+        let contents = fs::read_to_string(&path)
+            .map_err(|err| format!("Failed to read the checkpoint {}: {}", path.display(), err))?;
+        let checkpoint = Checkpoint::parse(&contents)
+            .ok_or_else(|| format!("{} is not a sweep checkpoint", path.display()))?;
+        if checkpoint.master != fingerprint(&master) {
+            return Err("The checkpoint was made with another master seed".to_string());
+        }
+        if checkpoint.mode != mode {
+            return Err(format!(
+                "The checkpoint was made in {} mode, not {} mode",
+                checkpoint.mode, mode
+            ));
+        }
+        if checkpoint.keyspace != keyspace_fingerprint(keyspace) {
+            return Err(
+                "The checkpoint was made with another mnemonic prefix, program, transaction, \
+                 multisig account or split-key request"
+                    .to_string(),
+            );
+        }
+        let sweep = Sweep::new(
+            master,
+            mode,
+            keyspace,
+            checkpoint.start,
+            checkpoint.end,
+            Some((path, interval)),
+        );
+        {
+            let mut state = sweep.state.lock().unwrap();
+            state.next = checkpoint.done;
+            state.min_keys = checkpoint.min_keys;
+        }
+        Ok(sweep)
+    }
+
+    /// The key base of a batch
+    pub fn key_base(&self, counter: u64) -> [u8; 32] {
+        let mut key_base = [0u8; 32];
+        key_base.copy_from_slice(
+            &Sha512_256::new()
+                .chain_update(self.master)
+                .chain_update(counter.to_le_bytes())
+                .finalize(),
+        );
+        key_base
+    }
+
+    /// Reserves the next batch, returning its counter and key base. None once
+    /// every batch of the range was handed out.
+    pub fn next(&self) -> Option<(u64, [u8; 32])> {
+        let mut state = self.state.lock().unwrap();
+        let counter = match state.retry.pop() {
+            Some(counter) => counter,
+            None if state.next < self.end => {
+                state.next += 1;
+                state.next - 1
+            }
+            None => return None,
+        };
+        state.pending.insert(counter);
+        Some((counter, self.key_base(counter)))
+    }
+
+    /// Records that the batch of `counter` checked `keys` keys, saving a
+    /// checkpoint when one is due or the sweep is complete
+    pub fn finish(&self, counter: u64, keys: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.pending.remove(&counter);
+        state.min_keys = Some(state.min_keys.map_or(keys, |min_keys| min_keys.min(keys)));
+        state.unsaved += 1;
+        if let Some((ref path, interval)) = self.checkpoint {
+            if state.unsaved >= interval || self.is_complete(&state) {
+                if let Err(err) = self.save(&state, path) {
+                    eprintln!("\nFailed to save the checkpoint {}: {}", path.display(), err);
+                }
+                state.unsaved = 0;
+            }
+        }
+    }
+
+    /// Hands the batch of `counter` out again, after a device lost it
+    pub fn abandon(&self, counter: u64) {
+        let mut state = self.state.lock().unwrap();
+        if state.pending.remove(&counter) {
+            state.retry.push(counter);
+        }
+    }
+
+    /// Whether every batch of the range finished
+    pub fn complete(&self) -> bool {
+        self.is_complete(&self.state.lock().unwrap())
+    }
+
+    fn is_complete(&self, state: &State) -> bool {
+        done(state) == self.end
+    }
+
+    fn save(&self, state: &State, path: &PathBuf) -> io::Result<()> {
+        let checkpoint = Checkpoint {
+            master: fingerprint(&self.master),
+            mode: self.mode.to_string(),
+            keyspace: self.keyspace.clone(),
+            start: self.start,
+            done: done(state),
+            end: self.end,
+            min_keys: state.min_keys,
+        };
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, checkpoint.format())?;
+        fs::rename(&tmp_path, path)
+    }
+
+    /// What the sweep covered so far, for the user
+    pub fn summary(&self) -> String {
+        let state = self.state.lock().unwrap();
+        match state.min_keys {
+            None => format!("Sweeping batches {}..{}", self.start, self.end),
+            Some(min_keys) => format!(
+                "Batches {}..{} of the sweep {}..{} were searched, at least {} keys each",
+                self.start,
+                done(&state),
+                self.start,
+                self.end,
+                min_keys
+            ),
+        }
+    }
+}
+
+/// The counter below which every batch finished
+fn done(state: &State) -> u64 {
+    state
+        .pending
+        .iter()
+        .chain(state.retry.iter())
+        .cloned()
+        .min()
+        .unwrap_or(state.next)
+}
+
+/// Identifies the master seed in checkpoints without revealing it
+fn fingerprint(master: &[u8; 32]) -> String {
+    hex::encode(&Sha512_256::digest(master)[..8])
+}
+
+/// Identifies the keyspace in checkpoints. Each part is length prefixed, so
+/// different splits of the same bytes don't collide.
+fn keyspace_fingerprint(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha512_256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hex::encode(&hasher.finalize()[..8])
+}
+
+// One "key value" pair per line
+impl Checkpoint {
+    fn parse(contents: &str) -> Option<Checkpoint> {
+        let value = |key: &str| {
+            contents
+                .lines()
+                .filter_map(|line| line.split_once(' '))
+                .find(|&(k, _)| k == key)
+                .map(|(_, value)| value.to_string())
+        };
+        let min_keys = value("min-keys")?;
+        Some(Checkpoint {
+            master: value("master")?,
+            mode: value("mode")?,
+            keyspace: value("keyspace")?,
+            start: value("start")?.parse().ok()?,
+            done: value("done")?.parse().ok()?,
+            end: value("end")?.parse().ok()?,
+            min_keys: if min_keys == "-" {
+                None
+            } else {
+                Some(min_keys.parse().ok()?)
+            },
+        })
+    }
+
+    fn format(&self) -> String {
+        format!(
+            "master {}\nmode {}\nkeyspace {}\nstart {}\ndone {}\nend {}\nmin-keys {}\n",
+            self.master,
+            self.mode,
+            self.keyspace,
+            self.start,
+            self.done,
+            self.end,
+            self.min_keys.map_or("-".to_string(), |keys| keys.to_string())
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn test_sweep() {
+        let path = env::temp_dir().join(format!("algomania-sweep-{}", std::process::id()));
+        let sweep = Sweep::new([7u8; 32], "seed", &[b"prefix"], 10, 14, Some((path.clone(), 2)));
+        let (first, key_base) = sweep.next().unwrap();
+        assert_eq!(first, 10);
+        assert_eq!(key_base, Sweep::new([7u8; 32], "seed", &[], 0, 1, None).key_base(10));
+        assert_ne!(key_base, sweep.key_base(11));
+        assert_eq!(sweep.next().unwrap().0, 11);
+        sweep.abandon(first);
+        assert_eq!(sweep.next().unwrap().0, 10);
+        sweep.finish(11, 100);
+        sweep.finish(10, 80);
+        assert!(!sweep.complete());
+
+        // the checkpoint covers 10 and 11, the resumed sweep goes on with 12
+        let resumed = Sweep::resume([7u8; 32], "seed", &[b"prefix"], path.clone(), 2).unwrap();
+        assert_eq!(resumed.next().unwrap().0, 12);
+        assert_eq!(resumed.next().unwrap().0, 13);
+        assert_eq!(resumed.next(), None);
+        resumed.finish(13, 90);
+        resumed.finish(12, 90);
+        assert!(resumed.complete());
+        let checkpoint = Checkpoint::parse(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!((checkpoint.start, checkpoint.done, checkpoint.end), (10, 14, 14));
+        assert_eq!(checkpoint.min_keys, Some(80));
+
+        assert!(Sweep::resume([8u8; 32], "seed", &[b"prefix"], path.clone(), 2).is_err());
+        assert!(Sweep::resume([7u8; 32], "txid", &[b"prefix"], path.clone(), 2).is_err());
+        // the same key bases with another prefix or template are other keys
+        assert!(Sweep::resume([7u8; 32], "seed", &[b"other"], path.clone(), 2).is_err());
+        assert!(Sweep::resume([7u8; 32], "seed", &[], path.clone(), 2).is_err());
+        assert!(Sweep::resume([7u8; 32], "seed", &[b"pre", b"fix"], path.clone(), 2).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
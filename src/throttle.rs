@@ -0,0 +1,173 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// How often the control file is checked for changes
+const CONTROL_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Limits how busy a GPU is kept, so a shared workstation stays usable. The
+/// limits can be changed while the search runs, see `watch_control_file`.
+pub struct Throttle {
+    /// The target busy fraction of the device in percent
+    max_utilization: AtomicUsize,
+    /// The longest a single launch may take in milliseconds, 0 for no limit
+    max_latency_ms: AtomicUsize,
+}
+
+impl Throttle {
+    pub fn new(max_utilization: usize, max_latency_ms: usize) -> Result<Throttle, String> {
+        let throttle = Throttle {
+            max_utilization: AtomicUsize::new(100),
+            max_latency_ms: AtomicUsize::new(0),
+        };
+        throttle.set_max_utilization(max_utilization)?;
+        throttle.max_latency_ms.store(max_latency_ms, Ordering::Relaxed);
+        Ok(throttle)
+    }
+
+    fn set_max_utilization(&self, percent: usize) -> Result<(), String> {
+        if percent == 0 || percent > 100 {
+            return Err(format!(
+                "The maximum utilization must be between 1 and 100 percent, not {}",
+                percent
+            ));
+        }
+        self.max_utilization.store(percent, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Whether any limit is set. Without one, the pipeline keeps several
+    /// launches in flight and the device never waits for the host.
+    pub fn is_active(&self) -> bool {
+        self.max_utilization.load(Ordering::Relaxed) < 100 || self.max_latency().is_some()
+    }
+
+    pub fn max_latency(&self) -> Option<Duration> {
+        match self.max_latency_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(Duration::from_millis(ms as u64)),
+        }
+    }
+
+    /// How long to leave the device idle after a launch that kept it busy
+    /// for `busy`, to hit the target utilization
+    pub fn idle_time(&self, busy: Duration) -> Duration {
+        let percent = self.max_utilization.load(Ordering::Relaxed) as u32;
+        busy * (100 - percent) / percent
+    }
+
+    /// Applies a line of the control file, either `max-utilization PERCENT`
+    /// or `max-latency MILLISECONDS` (0 or `off` to remove the limit).
+    /// Empty lines and lines starting with `#` are ignored.
+    pub fn apply_command(&self, line: &str) -> Result<(), String> {
+        let mut words = line.split_whitespace();
+        let (command, value) = match (words.next(), words.next(), words.next()) {
+            (None, _, _) => return Ok(()),
+            (Some(command), _, _) if command.starts_with('#') => return Ok(()),
+            (Some(command), Some(value), None) => (command, value),
+            _ => return Err(format!("Invalid control command: {}", line)),
+        };
+        match command {
+            "max-utilization" => {
+                let percent = value
+                    .trim_end_matches('%')
+                    .parse()
+                    .map_err(|_| format!("Invalid utilization: {}", value))?;
+                self.set_max_utilization(percent)
+            }
+            "max-latency" => {
+                let ms = match value {
+                    "off" => 0,
+                    value => value
+                        .parse()
+                        .map_err(|_| format!("Invalid latency: {}", value))?,
+                };
+                self.max_latency_ms.store(ms, Ordering::Relaxed);
+                Ok(())
+            }
+            _ => Err(format!("Unknown control command: {}", command)),
+        }
+    }
+}
+
+/// Re-reads `path` whenever it changes and applies its commands to
+/// `throttle`, so the limits can be adjusted without restarting the search.
+pub fn watch_control_file(path: PathBuf, throttle: Arc<Throttle>) {
+    thread::spawn(move || {
+        let mut last_modified: Option<SystemTime> = None;
+        loop {
+            let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok();
+            if modified.is_some() && modified != last_modified {
+                last_modified = modified;
+                match fs::read_to_string(&path) {
+                    Ok(contents) => {
+                        for line in contents.lines() {
+                            if let Err(err) = throttle.apply_command(line) {
+                                eprintln!("\n{}: {}", path.display(), err);
+                            }
+                        }
+                    }
+                    Err(err) => eprintln!("\nFailed to read {}: {}", path.display(), err),
+                }
+            }
+            thread::sleep(CONTROL_POLL_INTERVAL);
+        }
+    });
+}
+
+/// The number of threads for the next launch, so it takes a bit less than
+/// `target` when the last one with `threads` took `latency`. Stays a multiple
+/// of `granularity` and within `granularity..=max_threads`, and at most
+/// doubles per launch.
+pub fn adapt_threads(
+    threads: usize,
+    max_threads: usize,
+    granularity: usize,
+    latency: Duration,
+    target: Duration,
+) -> usize {
+    let scale = 0.8 * target.as_secs_f64() / latency.as_secs_f64().max(1e-6);
+    let wanted = (threads as f64 * scale.min(2.0)) as usize;
+    let wanted = wanted.min(max_threads) / granularity * granularity;
+    wanted.max(granularity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands() {
+        let throttle = Throttle::new(100, 0).unwrap();
+        assert!(!throttle.is_active());
+        throttle.apply_command("max-utilization 25%").unwrap();
+        assert!(throttle.is_active());
+        assert_eq!(
+            throttle.idle_time(Duration::from_millis(10)),
+            Duration::from_millis(30)
+        );
+        throttle.apply_command("max-latency 16").unwrap();
+        assert_eq!(throttle.max_latency(), Some(Duration::from_millis(16)));
+        throttle.apply_command("max-latency off").unwrap();
+        throttle.apply_command("# comment").unwrap();
+        throttle.apply_command("").unwrap();
+        assert_eq!(throttle.max_latency(), None);
+        assert!(throttle.apply_command("max-utilization 0").is_err());
+        assert!(throttle.apply_command("max-utilization").is_err());
+        assert!(throttle.apply_command("turbo 1").is_err());
+    }
+
+    #[test]
+    fn test_adapt_threads() {
+        let ms = Duration::from_millis;
+        // too slow: scale down, keeping a multiple of the local work size
+        assert_eq!(adapt_threads(1 << 20, 1 << 20, 64, ms(100), ms(10)), 83840);
+        // fast: at most double, never beyond the configured threads
+        assert_eq!(adapt_threads(1 << 16, 1 << 20, 64, ms(1), ms(100)), 1 << 17);
+        assert_eq!(adapt_threads(1 << 20, 1 << 20, 64, ms(1), ms(100)), 1 << 20);
+        assert_eq!(adapt_threads(64, 1 << 20, 64, ms(1000), ms(1)), 64);
+    }
+}
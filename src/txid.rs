@@ -0,0 +1,659 @@
+use data_encoding::BASE64;
+use serde_json::Value;
+use sha2::{Digest, Sha512_256};
+
+use derivation;
+use logicsig;
+
+/// The domain separation prefixes of transaction and group IDs
+const TX_PREFIX: &[u8] = b"TX";
+const GROUP_PREFIX: &[u8] = b"TG";
+
+/// The largest note the protocol accepts
+const MAX_NOTE_SIZE: usize = 1024;
+
+/// The most transactions an atomic group can have
+const MAX_GROUP_SIZE: usize = 16;
+
+/// The bytes appended to the note, which the search varies
+pub const NONCE_SIZE: usize = 8;
+
+/// Fields whose values `goal clerk inspect` and the SDKs print as base64
+const BINARY_FIELDS: &[&str] = &[
+    "am", "apaa", "apap", "apsu", "gh", "grp", "lx", "n", "note", "selkey", "sprfkey", "votekey",
+];
+
+/// Fields holding addresses, which are encoded as their public keys. The one
+/// letter ones are the manager, reserve, freeze and clawback of asset params.
+const ADDRESS_FIELDS: &[&str] = &[
+    "aclose", "apat", "arcv", "asnd", "c", "close", "f", "fadd", "m", "r", "rcv", "rekey", "snd",
+];
+
+/// The fields of a msgpack map with their encoded values, sorted by key
+type Map = Vec<(String, Vec<u8>)>;
+
+/// An unsigned transaction, or a group of them, with a nonce at the end of
+/// the note of one transaction, which the search varies until the
+/// transaction or group ID matches
+#[derive(PartialEq, Eq, Debug)]
+pub struct Template {
+    /// The transactions without group ID, the varied one with a zero nonce
+    transactions: Vec<Map>,
+    /// The index of the varied transaction
+    index: usize,
+    /// "TX" || the varied transaction, followed by "TG" || the group with
+    /// zeros as the ID of the varied transaction if there are several
+    message: Vec<u8>,
+    /// The length of the transaction part of `message`
+    tx_len: usize,
+    /// Where the nonce starts in `message`
+    nonce_pos: usize,
+    /// Where the ID of the varied transaction starts in the group part of
+    /// `message`
+    group_pos: usize,
+}
+
+impl Template {
+    /// `contents` are one or more unsigned transactions, either as
+    /// concatenated msgpack like `goal clerk send -o` writes, or as JSON like
+    /// `goal clerk inspect` prints. Several transactions form a group, whose
+    /// ID replaces the IDs they had. `index` selects the transaction whose
+    /// note is varied.
+    pub fn new(contents: &[u8], index: usize) -> Result<Template, String> {
+        let mut transactions = read_transactions(contents)?;
+        if transactions.len() > MAX_GROUP_SIZE {
+            return Err(format!(
+                "A group has at most {} transactions, the file has {}",
+                MAX_GROUP_SIZE,
+                transactions.len()
+            ));
+        }
+        if index >= transactions.len() {
+            return Err(format!(
+                "There is no transaction {} in a file of {} transactions",
+                index,
+                transactions.len()
+            ));
+        }
+        let grouped = transactions.len() > 1;
+        if grouped {
+            for transaction in &mut transactions {
+                transaction.retain(|(key, _)| key != "grp");
+            }
+        }
+
+        let mut note = match field(&transactions[index], "note") {
+            Some(value) => Reader::new(value).bin()?.to_vec(),
+            None => Vec::new(),
+        };
+        note.extend_from_slice(&[0u8; NONCE_SIZE]);
+        if note.len() > MAX_NOTE_SIZE {
+            return Err(format!(
+                "Notes have at most {} bytes, with the nonce this one has {}",
+                MAX_NOTE_SIZE,
+                note.len()
+            ));
+        }
+        let mut value = Vec::new();
+        write_bin(&mut value, &note);
+        set_field(&mut transactions[index], "note", value);
+
+        let mut message = TX_PREFIX.to_vec();
+        message.extend_from_slice(&encode_map(&transactions[index]));
+        let tx_len = message.len();
+        let nonce_pos = TX_PREFIX.len() + value_end(&transactions[index], "note") - NONCE_SIZE;
+
+        let mut group_pos = 0;
+        if grouped {
+            message.extend_from_slice(GROUP_PREFIX);
+            write_map_header(&mut message, 1);
+            write_str(&mut message, "txlist");
+            write_array_header(&mut message, transactions.len());
+            for (i, transaction) in transactions.iter().enumerate() {
+                let mut id = [0u8; 32];
+                if i != index {
+                    id = transaction_id(&encode_map(transaction));
+                }
+                write_bin(&mut message, &id);
+                if i == index {
+                    group_pos = message.len() - tx_len - 32;
+                }
+            }
+        }
+        Ok(Template {
+            transactions,
+            index,
+            message,
+            tx_len,
+            nonce_pos,
+            group_pos,
+        })
+    }
+
+    /// The transaction message, followed by the group message if any
+    pub fn message(&self) -> &[u8] {
+        &self.message
+    }
+
+    /// The length of the transaction message
+    pub fn tx_len(&self) -> usize {
+        self.tx_len
+    }
+
+    pub fn nonce_pos(&self) -> usize {
+        self.nonce_pos
+    }
+
+    /// The length of the group message, 0 without a group
+    pub fn group_len(&self) -> usize {
+        self.message.len() - self.tx_len
+    }
+
+    /// Where the ID of the varied transaction starts in the group message
+    pub fn group_pos(&self) -> usize {
+        self.group_pos
+    }
+
+    pub fn is_group(&self) -> bool {
+        self.group_len() != 0
+    }
+
+    /// The nonce a launch checks at `offset` from `root`
+    pub fn nonce_at_offset(&self, root: &[u8; 32], offset: u64) -> Vec<u8> {
+        logicsig::nonce_at_offset(root, NONCE_SIZE, offset)
+    }
+
+    /// The ID of the varied transaction with `nonce`
+    fn transaction_id(&self, nonce: &[u8]) -> [u8; 32] {
+        let mut id = [0u8; 32];
+        id.copy_from_slice(
+            &Sha512_256::new()
+                .chain_update(&self.message[..self.nonce_pos])
+                .chain_update(nonce)
+                .chain_update(&self.message[self.nonce_pos + NONCE_SIZE..self.tx_len])
+                .finalize(),
+        );
+        id
+    }
+
+    /// The group ID with `nonce`, or the transaction ID without a group
+    pub fn id(&self, nonce: &[u8]) -> [u8; 32] {
+        let txid = self.transaction_id(nonce);
+        if !self.is_group() {
+            return txid;
+        }
+        let group = &self.message[self.tx_len..];
+        let mut id = [0u8; 32];
+        id.copy_from_slice(
+            &Sha512_256::new()
+                .chain_update(&group[..self.group_pos])
+                .chain_update(txid)
+                .chain_update(&group[self.group_pos + 32..])
+                .finalize(),
+        );
+        id
+    }
+
+    /// The unsigned transactions with `nonce` and the group ID, as
+    /// concatenated msgpack that `goal clerk sign` accepts
+    pub fn transactions(&self, nonce: &[u8]) -> Vec<u8> {
+        let group_id = self.id(nonce);
+        let mut out = Vec::new();
+        for (i, transaction) in self.transactions.iter().enumerate() {
+            let mut transaction = transaction.clone();
+            if i == self.index {
+                let mut value = field(&transaction, "note").unwrap().to_vec();
+                let len = value.len();
+                value[len - NONCE_SIZE..].copy_from_slice(nonce);
+                set_field(&mut transaction, "note", value);
+            }
+            if self.is_group() {
+                let mut value = Vec::new();
+                write_bin(&mut value, &group_id);
+                set_field(&mut transaction, "grp", value);
+            }
+            write_map_header(&mut out, 1);
+            write_str(&mut out, "txn");
+            out.extend_from_slice(&encode_map(&transaction));
+        }
+        out
+    }
+}
+
+fn transaction_id(encoded: &[u8]) -> [u8; 32] {
+    let mut id = [0u8; 32];
+    id.copy_from_slice(
+        &Sha512_256::new()
+            .chain_update(TX_PREFIX)
+            .chain_update(encoded)
+            .finalize(),
+    );
+    id
+}
+
+fn read_transactions(contents: &[u8]) -> Result<Vec<Map>, String> {
+    let is_json = contents
+        .iter()
+        .find(|byte| !byte.is_ascii_whitespace())
+        .is_some_and(|&byte| byte == b'{' || byte == b'[');
+    let mut objects = Vec::new();
+    if is_json {
+        let value: Value =
+            serde_json::from_slice(contents).map_err(|err| format!("Invalid JSON: {}", err))?;
+        let values = match value {
+            Value::Array(values) => values,
+            value => vec![value],
+        };
+        for value in &values {
+            let mut object = Vec::new();
+            json_to_msgpack(None, value, &mut object)?;
+            objects.push(object);
+        }
+    } else {
+        let mut reader = Reader::new(contents);
+        while reader.pos < contents.len() {
+            objects.push(reader.value()?.to_vec());
+        }
+    }
+    if objects.is_empty() {
+        return Err("The file contains no transactions".to_string());
+    }
+    objects
+        .iter()
+        .map(|object| unwrap_transaction(Reader::new(object).map()?))
+        .collect()
+}
+
+/// The transaction of a `{"txn": ...}` wrapper, or the map itself if it is
+/// not wrapped
+fn unwrap_transaction(map: Map) -> Result<Map, String> {
+    if map
+        .iter()
+        .any(|(key, _)| key == "sig" || key == "msig" || key == "lsig")
+    {
+        return Err("The transaction is signed, changing its note would invalidate it".to_string());
+    }
+    let mut transaction = match field(&map, "txn") {
+        Some(value) => Reader::new(value).map()?,
+        None => map,
+    };
+    if field(&transaction, "type").is_none() {
+        return Err("The file contains something that is not a transaction".to_string());
+    }
+    transaction.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(transaction)
+}
+
+fn field<'a>(map: &'a Map, key: &str) -> Option<&'a [u8]> {
+    map.iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, value)| &value[..])
+}
+
+/// Sets the encoded value of `key`, keeping the keys sorted
+fn set_field(map: &mut Map, key: &str, value: Vec<u8>) {
+    match map.binary_search_by(|(k, _)| k.as_str().cmp(key)) {
+        Ok(pos) => map[pos].1 = value,
+        Err(pos) => map.insert(pos, (key.to_string(), value)),
+    }
+}
+
+fn encode_map(map: &Map) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_map_header(&mut out, map.len());
+    for (key, value) in map {
+        write_str(&mut out, key);
+        out.extend_from_slice(value);
+    }
+    out
+}
+
+/// Where the value of `key` ends in `encode_map(map)`
+fn value_end(map: &Map, key: &str) -> usize {
+    let mut out = Vec::new();
+    write_map_header(&mut out, map.len());
+    for (k, value) in map {
+        write_str(&mut out, k);
+        out.extend_from_slice(value);
+        if k == key {
+            break;
+        }
+    }
+    out.len()
+}
+
+/// Values the canonical encoding omits
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Bool(b) => !b,
+        Value::Number(n) => n.as_u64() == Some(0),
+        Value::String(s) => s.is_empty(),
+        Value::Array(values) => values.is_empty(),
+        Value::Object(map) => map.is_empty(),
+    }
+}
+
+/// Encodes JSON the way the protocol encodes the object it describes:
+/// sorted keys without empty values, address and base64 fields as binary
+fn json_to_msgpack(key: Option<&str>, value: &Value, out: &mut Vec<u8>) -> Result<(), String> {
+    match value {
+        Value::Null => out.push(0xc0),
+        Value::Bool(b) => out.push(if *b { 0xc3 } else { 0xc2 }),
+        Value::Number(n) => write_uint(
+            out,
+            n.as_u64()
+                .ok_or_else(|| format!("{} is not an unsigned integer", n))?,
+        ),
+        Value::String(s) => {
+            if key.is_some_and(|key| BINARY_FIELDS.contains(&key)) {
+                let bytes = BASE64
+                    .decode(s.as_bytes())
+                    .map_err(|_| format!("{} is not valid base64", s))?;
+                write_bin(out, &bytes);
+            } else if key.is_some_and(|key| ADDRESS_FIELDS.contains(&key)) {
+                write_bin(out, &derivation::address_to_pubkey(s)?);
+            } else {
+                write_str(out, s);
+            }
+        }
+        Value::Array(values) => {
+            write_array_header(out, values.len());
+            for value in values {
+                json_to_msgpack(key, value, out)?;
+            }
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> =
+                map.iter().filter(|(_, value)| !is_empty(value)).collect();
+            entries.sort_by_key(|(key, _)| *key);
+            write_map_header(out, entries.len());
+            for (key, value) in entries {
+                write_str(out, key);
+                json_to_msgpack(Some(key), value, out)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_map_header(out: &mut Vec<u8>, len: usize) {
+    if len < 16 {
+        out.push(0x80 | len as u8);
+    } else {
+        out.push(0xde);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    }
+}
+
+fn write_array_header(out: &mut Vec<u8>, len: usize) {
+    if len < 16 {
+        out.push(0x90 | len as u8);
+    } else if len <= u16::MAX as usize {
+        out.push(0xdc);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(0xdd);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+}
+
+fn write_str(out: &mut Vec<u8>, s: &str) {
+    let len = s.len();
+    if len < 32 {
+        out.push(0xa0 | len as u8);
+    } else if len <= u8::MAX as usize {
+        out.push(0xd9);
+        out.push(len as u8);
+    } else if len <= u16::MAX as usize {
+        out.push(0xda);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(0xdb);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+    out.extend_from_slice(s.as_bytes());
+}
+
+fn write_bin(out: &mut Vec<u8>, bytes: &[u8]) {
+    let len = bytes.len();
+    if len <= u8::MAX as usize {
+        out.push(0xc4);
+        out.push(len as u8);
+    } else if len <= u16::MAX as usize {
+        out.push(0xc5);
+        out.extend_from_slice(&(len as u16).to_be_bytes());
+    } else {
+        out.push(0xc6);
+        out.extend_from_slice(&(len as u32).to_be_bytes());
+    }
+    out.extend_from_slice(bytes);
+}
+
+fn write_uint(out: &mut Vec<u8>, n: u64) {
+    if n < 0x80 {
+        out.push(n as u8);
+    } else if n <= u8::MAX as u64 {
+        out.push(0xcc);
+        out.push(n as u8);
+    } else if n <= u16::MAX as u64 {
+        out.push(0xcd);
+        out.extend_from_slice(&(n as u16).to_be_bytes());
+    } else if n <= u32::MAX as u64 {
+        out.push(0xce);
+        out.extend_from_slice(&(n as u32).to_be_bytes());
+    } else {
+        out.push(0xcf);
+        out.extend_from_slice(&n.to_be_bytes());
+    }
+}
+
+/// Just enough msgpack decoding to split transactions into their fields
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Reader<'a> {
+        Reader { bytes, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() - self.pos < len {
+            return Err("Truncated msgpack".to_string());
+        }
+        self.pos += len;
+        Ok(&self.bytes[self.pos - len..self.pos])
+    }
+
+    /// A big endian length of `size` bytes
+    fn len(&mut self, size: usize) -> Result<usize, String> {
+        Ok(self
+            .take(size)?
+            .iter()
+            .fold(0, |len, &byte| (len << 8) | byte as usize))
+    }
+
+    /// The encoded bytes of the next value
+    fn value(&mut self) -> Result<&'a [u8], String> {
+        let start = self.pos;
+        self.skip()?;
+        Ok(&self.bytes[start..self.pos])
+    }
+
+    fn skip(&mut self) -> Result<(), String> {
+        let marker = self.take(1)?[0];
+        let (skip, items) = match marker {
+            0x80..=0x8f => (0, 2 * (marker & 0x0f) as usize),
+            0x90..=0x9f => (0, (marker & 0x0f) as usize),
+            0xa0..=0xbf => ((marker & 0x1f) as usize, 0),
+            0xc1 => return Err("Invalid msgpack".to_string()),
+            0xc4 | 0xd9 => (self.len(1)?, 0),
+            0xc5 | 0xda => (self.len(2)?, 0),
+            0xc6 | 0xdb => (self.len(4)?, 0),
+            0xc7 => (self.len(1)? + 1, 0),
+            0xc8 => (self.len(2)? + 1, 0),
+            0xc9 => (self.len(4)? + 1, 0),
+            0xcc | 0xd0 => (1, 0),
+            0xcd | 0xd1 | 0xd4 => (2, 0),
+            0xd5 => (3, 0),
+            0xca | 0xce | 0xd2 => (4, 0),
+            0xd6 => (5, 0),
+            0xcb | 0xcf | 0xd3 => (8, 0),
+            0xd7 => (9, 0),
+            0xd8 => (17, 0),
+            0xdc => (0, self.len(2)?),
+            0xdd => (0, self.len(4)?),
+            0xde => (0, 2 * self.len(2)?),
+            0xdf => (0, 2 * self.len(4)?),
+            _ => (0, 0),
+        };
+        self.take(skip)?;
+        for _ in 0..items {
+            self.skip()?;
+        }
+        Ok(())
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let marker = self.take(1)?[0];
+        let len = match marker {
+            0xa0..=0xbf => (marker & 0x1f) as usize,
+            0xd9 => self.len(1)?,
+            0xda => self.len(2)?,
+            0xdb => self.len(4)?,
+            _ => return Err("Expected a msgpack string".to_string()),
+        };
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| "Invalid msgpack string".to_string())
+    }
+
+    fn bin(&mut self) -> Result<&'a [u8], String> {
+        let len = match self.take(1)?[0] {
+            0xc4 => self.len(1)?,
+            0xc5 => self.len(2)?,
+            0xc6 => self.len(4)?,
+            _ => return Err("Expected msgpack binary".to_string()),
+        };
+        self.take(len)
+    }
+
+    fn map(&mut self) -> Result<Map, String> {
+        let marker = self.take(1)?[0];
+        let len = match marker {
+            0x80..=0x8f => (marker & 0x0f) as usize,
+            0xde => self.len(2)?,
+            0xdf => self.len(4)?,
+            _ => return Err("Expected a msgpack map".to_string()),
+        };
+        (0..len)
+            .map(|_| Ok((self.string()?, self.value()?.to_vec())))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data_encoding::BASE32_NOPAD;
+
+    const SENDER: &str = "7ZUECA7HFLZTXENRV24SHLU4AVPUTMTTDUFUBNBD64C73F3UHRTHAIOF6Q";
+    const RECEIVER: &str = "PPNHVQJGE6ZMEWPR35UHLUYMCCZV6VNTHLJMZDVCONXKUPV47K4WGQJWGI";
+
+    fn payment(amount: u64, note: &str) -> String {
+        format!(
+            r#"{{"txn": {{"amt": {}, "fee": 1000, "fv": 1, "lv": 1001, "gh": "SGO1GKSzyE7IEPItTxCByw9x8FmnrCDexi9/cOUJOiI=", "note": "{}", "rcv": "{}", "snd": "{}", "type": "pay"}}}}"#,
+            amount, note, SENDER, SENDER
+        )
+    }
+
+    #[test]
+    fn test_template() {
+        let json = payment(5, "aGk=");
+        let template = Template::new(json.as_bytes(), 0).unwrap();
+        assert!(!template.is_group());
+        let nonce = [1, 2, 3, 4, 5, 6, 7, 8];
+        let signed = template.transactions(&nonce);
+        let mut reader = Reader::new(&signed);
+        let txn = unwrap_transaction(reader.map().unwrap()).unwrap();
+        assert_eq!(reader.pos, signed.len());
+        assert_eq!(Reader::new(field(&txn, "note").unwrap()).bin().unwrap(), b"hi\x01\x02\x03\x04\x05\x06\x07\x08");
+        assert_eq!(template.id(&nonce), transaction_id(&encode_map(&txn)));
+
+        // Reading the msgpack back appends another nonce
+        let again = Template::new(&signed, 0).unwrap();
+        assert_eq!(again.message().len(), template.message().len() + NONCE_SIZE);
+        assert_eq!(again.message()[again.nonce_pos() - NONCE_SIZE..again.nonce_pos()], nonce);
+
+        let again = Template::new(format!("[{}, {}]", json, payment(6, "")).as_bytes(), 1).unwrap();
+        assert!(again.is_group());
+
+        // Every transaction of the group carries the group ID of the txlist
+        let grouped = again.transactions(&nonce);
+        let mut reader = Reader::new(&grouped);
+        let mut group = GROUP_PREFIX.to_vec();
+        write_map_header(&mut group, 1);
+        write_str(&mut group, "txlist");
+        write_array_header(&mut group, 2);
+        for _ in 0..2 {
+            let mut txn = unwrap_transaction(reader.map().unwrap()).unwrap();
+            let grp = Reader::new(field(&txn, "grp").unwrap()).bin().unwrap().to_vec();
+            assert_eq!(grp, again.id(&nonce));
+            txn.retain(|(key, _)| key != "grp");
+            write_bin(&mut group, &transaction_id(&encode_map(&txn)));
+        }
+        assert_eq!(again.id(&nonce)[..], Sha512_256::digest(&group)[..]);
+
+        assert!(Template::new(json.as_bytes(), 1).is_err());
+        assert!(Template::new(br#"{"sig": "AA==", "txn": {"type": "pay"}}"#, 0).is_err());
+    }
+
+    #[test]
+    fn test_known_ids() {
+        // the IDs algonaut gives these transactions, with the nonce in the note
+        let pay = format!(
+            r#"{{"txn": {{"amt": 5, "fee": 1000, "fv": 1000, "gen": "testnet-v1.0", "gh": "SGO1GKSzyE7IEPItTxCByw9x8FmnrCDexi9/cOUJOiI=", "lv": 2000, "note": "aGk=", "rcv": "{}", "snd": "{}", "type": "pay"}}}}"#,
+            RECEIVER, SENDER
+        );
+        let axfer = format!(
+            r#"{{"txn": {{"aamt": 7, "arcv": "{}", "fee": 1000, "fv": 1000, "gen": "testnet-v1.0", "gh": "SGO1GKSzyE7IEPItTxCByw9x8FmnrCDexi9/cOUJOiI=", "lv": 2000, "snd": "{}", "type": "axfer", "xaid": 31566704}}}}"#,
+            SENDER, RECEIVER
+        );
+        let nonce = [1, 2, 3, 4, 5, 6, 7, 8];
+        let template = Template::new(pay.as_bytes(), 0).unwrap();
+        assert_eq!(
+            BASE32_NOPAD.encode(&template.id(&nonce)),
+            "CAU7EJ35O7BMW5FBAUQLRSQ4CDVGD6UTVG7OQISNVILLOPPGX6TQ"
+        );
+
+        let group = Template::new(format!("[{}, {}]", pay, axfer).as_bytes(), 0).unwrap();
+        assert_eq!(
+            BASE64.encode(&group.id(&nonce)),
+            "RdYPRox5Rb6KV+O7Fvg8X1bDGdguZ7/UkRLDttlm3Pk="
+        );
+        let grouped = group.transactions(&nonce);
+        let txn = unwrap_transaction(Reader::new(&grouped).map().unwrap()).unwrap();
+        assert_eq!(
+            BASE32_NOPAD.encode(&transaction_id(&encode_map(&txn))),
+            "BI4H222GFS72BIZRNVT4G6WRMYV7HMIRI2VNKTADA474ITKGVMGA"
+        );
+    }
+
+    #[test]
+    fn test_address_fields() {
+        let mut out = Vec::new();
+        // only address fields hold addresses
+        let json = format!(r#"{{"gen": "{}", "snd": "{}"}}"#, SENDER, SENDER);
+        let json: Value = serde_json::from_str(&json).unwrap();
+        json_to_msgpack(None, &json, &mut out).unwrap();
+        let map = Reader::new(&out).map().unwrap();
+        assert_eq!(Reader::new(field(&map, "gen").unwrap()).string().unwrap(), SENDER);
+        assert_eq!(
+            Reader::new(field(&map, "snd").unwrap()).bin().unwrap(),
+            derivation::address_to_pubkey(SENDER).unwrap()
+        );
+
+        let json: Value = serde_json::from_str(r#"{"rcv": "not an address"}"#).unwrap();
+        assert!(json_to_msgpack(None, &json, &mut Vec::new()).is_err());
+    }
+}
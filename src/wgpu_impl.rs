@@ -0,0 +1,294 @@
+use std::sync::mpsc;
+
+use byteorder::ByteOrder;
+use byteorder::LittleEndian;
+use pollster;
+use wgpu;
+use wgpu::util::DeviceExt;
+
+use gpu::GpuOptions;
+use gpu::SearchMode;
+
+const DEFAULT_WORKGROUP_SIZE: usize = 64;
+
+/// Size of the `Params` struct in wgsl/entry.wgsl
+const PARAMS_SIZE: usize = 3 * 32 + 4;
+
+pub struct WgpuGpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    bind_group: wgpu::BindGroup,
+    params: wgpu::Buffer,
+    result: wgpu::Buffer,
+    staging: wgpu::Buffer,
+    workgroups: (u32, u32),
+    threads: usize,
+}
+
+/// Parses ge25519_niels_base_multiples out of the OpenCL constants, so that
+/// both backends share a single copy of the table.
+fn niels_base_multiples() -> Vec<u8> {
+    let src = include_str!("opencl/curve25519-constants.cl");
+    let start = src
+        .find("ge25519_niels_base_multiples")
+        .expect("niels table missing from curve25519-constants.cl");
+    let src = &src[start..];
+    let src = &src[src.find('{').unwrap()..];
+    let table: Vec<u8> = src
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|t| t.starts_with("0x"))
+        .take(256 * 96)
+        .map(|t| u8::from_str_radix(&t[2..], 16).expect("Invalid niels table entry"))
+        .collect();
+    assert_eq!(table.len(), 256 * 96);
+    table
+}
+
+impl WgpuGpu {
+    pub fn new(opts: GpuOptions) -> Result<WgpuGpu, String> {
+        if opts.mode != SearchMode::Seed {
+            return Err("The wgpu backend only supports seed mode".into());
+        }
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::new_without_display_handle_from_env());
+        let adapters = pollster::block_on(instance.enumerate_adapters(wgpu::Backends::all()));
+        if adapters.is_empty() {
+            return Err("No wgpu adapters exist (check your Vulkan, Metal or DX12 setup)".into());
+        }
+        if opts.device_idx >= adapters.len() {
+            return Err(format!(
+                "Adapter index {} too large (max {})",
+                opts.device_idx,
+                adapters.len() - 1
+            ));
+        }
+        let adapter = &adapters[opts.device_idx];
+        let info = adapter.get_info();
+        eprintln!("Initializing wgpu adapter {} ({:?})", info.name, info.backend);
+
+        let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+            label: Some("algomania"),
+            required_limits: adapter.limits(),
+            ..Default::default()
+        }))
+        .map_err(|e| e.to_string())?;
+
+        let mut source = String::new();
+        source.push_str(include_str!("wgsl/sha512.wgsl"));
+        source.push_str(include_str!("wgsl/curve25519.wgsl"));
+        source.push_str(include_str!("wgsl/entry.wgsl"));
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("generate_pubkey"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+
+        let workgroup_size = opts.local_work_size.unwrap_or(DEFAULT_WORKGROUP_SIZE);
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("generate_pubkey"),
+            layout: None,
+            module: &module,
+            entry_point: Some("generate_pubkey"),
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants: &[("WORKGROUP_SIZE", workgroup_size as f64)],
+                ..Default::default()
+            },
+            cache: None,
+        });
+
+        // pub_req, pub_mask and prefix_len never change, only key_root is
+        // rewritten for every launch
+        let prefix_len = opts.matcher.prefix_len().min(32);
+        let mut params_data = [0u8; PARAMS_SIZE];
+        params_data[32..32 + prefix_len].copy_from_slice(&opts.matcher.req()[..prefix_len]);
+        params_data[64..64 + prefix_len].copy_from_slice(&opts.matcher.mask()[..prefix_len]);
+        LittleEndian::write_u32(&mut params_data[96..], prefix_len as u32);
+        let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("params"),
+            contents: &params_data,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let niels_table = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("niels_table"),
+            contents: &niels_base_multiples(),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let result = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("result"),
+            size: 4,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let staging = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("staging"),
+            size: 4,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        queue.write_buffer(&result, 0, &(!0u32).to_le_bytes());
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("generate_pubkey"),
+            layout: &pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: niels_table.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: result.as_entire_binding(),
+                },
+            ],
+        });
+
+        // Workgroups are spread over two dimensions once they exceed the
+        // per-dimension limit, the shader flattens them again.
+        let threads = opts.global_work_size.unwrap_or(opts.threads);
+        let groups = threads.div_ceil(workgroup_size);
+        let max_x = device.limits().max_compute_workgroups_per_dimension as usize;
+        let groups_x = groups.min(max_x).max(1);
+        let groups_y = groups.div_ceil(groups_x);
+        if groups_y > max_x {
+            return Err(format!("Too many wgpu threads: {}", threads));
+        }
+
+        Ok(WgpuGpu {
+            device,
+            queue,
+            pipeline,
+            bind_group,
+            params,
+            result,
+            staging,
+            workgroups: (groups_x as u32, groups_y as u32),
+            threads: groups_x * groups_y * workgroup_size,
+        })
+    }
+
+    /// The number of keys checked by every call to `compute`, which is the
+    /// requested thread count rounded up to whole workgroups.
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn compute(&mut self, out: &mut [u8], key_root: &[u8]) -> Result<bool, String> {
+        self.queue.write_buffer(&self.params, 0, &key_root[..32]);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &self.bind_group, &[]);
+            pass.dispatch_workgroups(self.workgroups.0, self.workgroups.1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&self.result, 0, &self.staging, 0, 4);
+        self.queue.submit(Some(encoder.finish()));
+
+        let (sender, receiver) = mpsc::channel();
+        self.staging
+            .map_async(wgpu::MapMode::Read, .., move |r| drop(sender.send(r)));
+        self.device
+            .poll(wgpu::PollType::wait_indefinitely())
+            .map_err(|e| e.to_string())?;
+        receiver
+            .recv()
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())?;
+        let thread = {
+            let view = self.staging.get_mapped_range(..).map_err(|e| e.to_string())?;
+            LittleEndian::read_u32(&view)
+        };
+        self.staging.unmap();
+
+        let success = thread != !0u32;
+        if success {
+            self.queue
+                .write_buffer(&self.result, 0, &(!0u32).to_le_bytes());
+            let base = LittleEndian::read_u64(key_root);
+            LittleEndian::write_u64(out, base.wrapping_add(thread as u64));
+            out[8..32].copy_from_slice(&key_root[8..32]);
+        }
+        Ok(success)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use derivation;
+    use pubkey_matcher::PubkeyMatcher;
+    use std::time::Duration;
+
+    #[test]
+    fn test_shader_validates() {
+        let mut source = String::new();
+        source.push_str(include_str!("wgsl/sha512.wgsl"));
+        source.push_str(include_str!("wgsl/curve25519.wgsl"));
+        source.push_str(include_str!("wgsl/entry.wgsl"));
+        let module = wgpu::naga::front::wgsl::parse_str(&source).expect("Failed to parse WGSL");
+        wgpu::naga::valid::Validator::new(
+            wgpu::naga::valid::ValidationFlags::all(),
+            wgpu::naga::valid::Capabilities::empty(),
+        )
+        .validate(&module)
+        .expect("Failed to validate WGSL");
+    }
+
+    #[test]
+    fn test_niels_table() {
+        let table = niels_base_multiples();
+        assert_eq!(&table[..4], &[0x3e, 0x91, 0x40, 0xd7]);
+    }
+
+    /// Runs on a software adapter such as lavapipe in CI, and is skipped when
+    /// no adapter is present.
+    #[test]
+    fn test_matches_cpu_derivation() {
+        let seed = [0x5au8; 32];
+        let pubkey = derivation::ed25519_privkey_to_pubkey(&seed);
+        // require the full public key, so only thread 0 can match
+        let matcher = PubkeyMatcher::new(pubkey.to_vec(), vec![0xff; 32]);
+        let mut gpu = match WgpuGpu::new(GpuOptions {
+            platform_idx: 0,
+            device_idx: 0,
+            threads: 256,
+            local_work_size: None,
+            global_work_size: None,
+            matcher: &matcher,
+            mode: SearchMode::Seed,
+            batch_size: 1,
+            iterations: 1,
+            use_tuned_profile: false,
+            launch_timeout: Duration::from_secs(0),
+            profile: false,
+            opencl_features: None,
+        }) {
+            Ok(gpu) => gpu,
+            Err(err) => {
+                eprintln!("Skipping wgpu test: {}", err);
+                return;
+            }
+        };
+        let mut found = [0u8; 32];
+        assert!(gpu.compute(&mut found, &seed).unwrap());
+        assert_eq!(found, seed);
+
+        // a thread in the middle of the launch must find its own key too
+        let mut key_root = seed;
+        key_root[0] = key_root[0].wrapping_sub(77);
+        let mut found = [0u8; 32];
+        assert!(gpu.compute(&mut found, &key_root).unwrap());
+        assert_eq!(found, seed);
+    }
+}
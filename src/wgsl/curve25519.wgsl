@@ -0,0 +1,238 @@
+// Field and group arithmetic for Ed25519 key derivation.
+//
+// Field elements use sixteen 16-bit limbs held in u32s, so that every limb
+// product fits into 32 bits. All functions return carried limbs (< 2^16),
+// the value itself is only fully reduced mod p by fe_tobytes.
+
+alias Fe = array<u32, 16>;
+
+struct Ge {
+	x: Fe,
+	y: Fe,
+	z: Fe,
+	t: Fe,
+}
+
+// {ysubx, xaddy, t2d}, the layout of ge25519_niels_base_multiples
+struct GeNiels {
+	ysubx: Fe,
+	xaddy: Fe,
+	t2d: Fe,
+}
+
+const FE_D = array<u32, 16>(
+	0x78a3u, 0x1359u, 0x4dcau, 0x75ebu, 0xd8abu, 0x4141u, 0x0a4du, 0x0070u,
+	0xe898u, 0x7779u, 0x4079u, 0x8cc7u, 0xfe73u, 0x2b6fu, 0x6ceeu, 0x5203u
+);
+
+fn fe_zero() -> Fe {
+	var r: Fe;
+	return r;
+}
+
+fn fe_one() -> Fe {
+	var r: Fe;
+	r[0] = 1u;
+	return r;
+}
+
+// Propagates carries of limbs below 2^31 and folds the top carry back in,
+// using 2^256 = 38 mod p.
+fn fe_carry(a: Fe) -> Fe {
+	var r = a;
+	var c = 0u;
+	for (var i = 0u; i < 16u; i++) {
+		r[i] += c;
+		c = r[i] >> 16u;
+		r[i] &= 0xffffu;
+	}
+	r[0] += 38u * c;
+	c = 0u;
+	for (var i = 0u; i < 16u; i++) {
+		r[i] += c;
+		c = r[i] >> 16u;
+		r[i] &= 0xffffu;
+	}
+	// only reachable if the value wrapped to almost zero
+	r[0] += 38u * c;
+	return r;
+}
+
+fn fe_add(a: Fe, b: Fe) -> Fe {
+	var r: Fe;
+	for (var i = 0u; i < 16u; i++) {
+		r[i] = a[i] + b[i];
+	}
+	return fe_carry(r);
+}
+
+// a - b + 4p, where every limb of 4p is at least 2^16 - 1
+fn fe_sub(a: Fe, b: Fe) -> Fe {
+	var r: Fe;
+	r[0] = a[0] + 0x3ffb4u - b[0];
+	for (var i = 1u; i < 15u; i++) {
+		r[i] = a[i] + 0x3fffcu - b[i];
+	}
+	r[15] = a[15] + 0x1fffcu - b[15];
+	return fe_carry(r);
+}
+
+fn fe_neg(a: Fe) -> Fe {
+	return fe_sub(fe_zero(), a);
+}
+
+fn fe_mul(a: Fe, b: Fe) -> Fe {
+	// 64-bit column sums as lo/hi pairs
+	var lo: array<u32, 32>;
+	var hi: array<u32, 32>;
+	for (var i = 0u; i < 16u; i++) {
+		for (var j = 0u; j < 16u; j++) {
+			let p = a[i] * b[j];
+			let k = i + j;
+			lo[k] += p;
+			hi[k] += select(0u, 1u, lo[k] < p);
+		}
+	}
+
+	var t: array<u32, 33>;
+	var c = 0u;
+	for (var k = 0u; k < 32u; k++) {
+		let l = lo[k] + c;
+		let h = hi[k] + select(0u, 1u, l < c);
+		t[k] = l & 0xffffu;
+		c = (l >> 16u) | (h << 16u);
+	}
+	t[32] = c;
+
+	var r: Fe;
+	for (var i = 0u; i < 16u; i++) {
+		r[i] = t[i] + 38u * t[i + 16u];
+	}
+	// 2^512 = 38^2 mod p
+	r[0] += 1444u * t[32];
+	return fe_carry(r);
+}
+
+fn fe_sq(a: Fe) -> Fe {
+	return fe_mul(a, a);
+}
+
+fn fe_sq_times(a: Fe, n: u32) -> Fe {
+	var r = a;
+	for (var i = 0u; i < n; i++) {
+		r = fe_sq(r);
+	}
+	return r;
+}
+
+// z^(p - 2), same addition chain as curve25519_recip
+fn fe_invert(z: Fe) -> Fe {
+	let z2 = fe_sq(z);
+	let z9 = fe_mul(fe_sq_times(z2, 2u), z);
+	let z11 = fe_mul(z9, z2);
+	let z2_5_0 = fe_mul(fe_sq(z11), z9);
+	let z2_10_0 = fe_mul(fe_sq_times(z2_5_0, 5u), z2_5_0);
+	let z2_20_0 = fe_mul(fe_sq_times(z2_10_0, 10u), z2_10_0);
+	let z2_40_0 = fe_mul(fe_sq_times(z2_20_0, 20u), z2_20_0);
+	let z2_50_0 = fe_mul(fe_sq_times(z2_40_0, 10u), z2_10_0);
+	let z2_100_0 = fe_mul(fe_sq_times(z2_50_0, 50u), z2_50_0);
+	let z2_200_0 = fe_mul(fe_sq_times(z2_100_0, 100u), z2_100_0);
+	let z2_250_0 = fe_mul(fe_sq_times(z2_200_0, 50u), z2_50_0);
+	return fe_mul(fe_sq_times(z2_250_0, 5u), z11);
+}
+
+// Fully reduces a mod p and returns it as eight little endian words.
+fn fe_tobytes(a: Fe) -> array<u32, 8> {
+	var r = a;
+	// fold bit 255 twice, afterwards r < 2^255
+	for (var n = 0u; n < 2u; n++) {
+		let top = r[15] >> 15u;
+		r[15] &= 0x7fffu;
+		r[0] += 19u * top;
+		var c = 0u;
+		for (var i = 0u; i < 16u; i++) {
+			r[i] += c;
+			c = r[i] >> 16u;
+			r[i] &= 0xffffu;
+		}
+	}
+	// subtract p if r + 19 reaches 2^255
+	var s: Fe;
+	var c = 19u;
+	for (var i = 0u; i < 16u; i++) {
+		s[i] = r[i] + c;
+		c = s[i] >> 16u;
+		s[i] &= 0xffffu;
+	}
+	if ((s[15] >> 15u) != 0u) {
+		s[15] &= 0x7fffu;
+		r = s;
+	}
+
+	var out: array<u32, 8>;
+	for (var i = 0u; i < 8u; i++) {
+		out[i] = r[2u * i] | (r[2u * i + 1u] << 16u);
+	}
+	return out;
+}
+
+fn fe_from_words(w: array<u32, 8>) -> Fe {
+	var r: Fe;
+	for (var i = 0u; i < 8u; i++) {
+		r[2u * i] = w[i] & 0xffffu;
+		r[2u * i + 1u] = w[i] >> 16u;
+	}
+	return r;
+}
+
+fn ge_identity() -> Ge {
+	var r: Ge;
+	r.x = fe_zero();
+	r.y = fe_one();
+	r.z = fe_one();
+	r.t = fe_zero();
+	return r;
+}
+
+// r = p + q, ge25519_nielsadd2 in curve25519.cl
+fn ge_add_niels(p: Ge, q: GeNiels) -> Ge {
+	let a = fe_mul(fe_sub(p.y, p.x), q.ysubx);
+	let b = fe_mul(fe_add(p.y, p.x), q.xaddy);
+	let c = fe_mul(p.t, q.t2d);
+	let d = fe_add(p.z, p.z);
+	let e = fe_sub(b, a);
+	let f = fe_sub(d, c);
+	let g = fe_add(d, c);
+	let h = fe_add(b, a);
+	var r: Ge;
+	r.x = fe_mul(e, f);
+	r.y = fe_mul(g, h);
+	r.z = fe_mul(f, g);
+	r.t = fe_mul(e, h);
+	return r;
+}
+
+fn ge_double(p: Ge) -> Ge {
+	let xx = fe_sq(p.x);
+	let yy = fe_sq(p.y);
+	let zz2 = fe_add(fe_sq(p.z), fe_sq(p.z));
+	let s = fe_sq(fe_add(p.x, p.y));
+	let y1 = fe_add(yy, xx);
+	let z1 = fe_sub(yy, xx);
+	let x1 = fe_sub(s, y1);
+	let t1 = fe_sub(zz2, z1);
+	var r: Ge;
+	r.x = fe_mul(x1, t1);
+	r.y = fe_mul(y1, z1);
+	r.z = fe_mul(z1, t1);
+	r.t = fe_mul(x1, y1);
+	return r;
+}
+
+fn ge_pack(p: Ge) -> array<u32, 8> {
+	let zi = fe_invert(p.z);
+	var out = fe_tobytes(fe_mul(p.y, zi));
+	let x = fe_tobytes(fe_mul(p.x, zi));
+	out[7] ^= (x[0] & 1u) << 31u;
+	return out;
+}
@@ -0,0 +1,123 @@
+// Port of generate_pubkey in opencl/entry.cl.
+//
+// params:
+//     key_root: the 32 byte key material base as little endian words. The
+//         thread ID is added to its first 8 bytes, like in the OpenCL kernel.
+//     prefix_len, req, mask: the public key prefix to look for.
+// niels_table:
+//     ge25519_niels_base_multiples from curve25519-constants.cl, as little
+//     endian words.
+// result:
+//     The smallest matching thread ID, 0xffffffff if none.
+
+struct Params {
+	key_root: array<u32, 8>,
+	req: array<u32, 8>,
+	mask: array<u32, 8>,
+	prefix_len: u32,
+}
+
+@group(0) @binding(0) var<storage, read> params: Params;
+@group(0) @binding(1) var<storage, read> niels_table: array<u32>;
+@group(0) @binding(2) var<storage, read_write> result: array<atomic<u32>, 1>;
+
+override WORKGROUP_SIZE: u32 = 64u;
+
+fn niels_entry(pos: u32, b: i32) -> GeNiels {
+	var r: GeNiels;
+	r.ysubx = fe_one();
+	r.xaddy = fe_one();
+	r.t2d = fe_zero();
+	let u = u32(select(b, -b, b < 0));
+	if (u == 0u) {
+		return r;
+	}
+	let base = (pos * 8u + u - 1u) * 24u;
+	var w: array<u32, 8>;
+	for (var i = 0u; i < 8u; i++) {
+		w[i] = niels_table[base + i];
+	}
+	r.ysubx = fe_from_words(w);
+	for (var i = 0u; i < 8u; i++) {
+		w[i] = niels_table[base + 8u + i];
+	}
+	r.xaddy = fe_from_words(w);
+	for (var i = 0u; i < 8u; i++) {
+		w[i] = niels_table[base + 16u + i];
+	}
+	r.t2d = fe_from_words(w);
+	// the first row of the table stores 2xy, without the factor d
+	if (pos == 0u) {
+		r.t2d = fe_mul(r.t2d, FE_D);
+	}
+	if (b < 0) {
+		let t = r.ysubx;
+		r.ysubx = r.xaddy;
+		r.xaddy = t;
+		r.t2d = fe_neg(r.t2d);
+	}
+	return r;
+}
+
+// [s]basepoint for a clamped scalar, which is below 2^255 so the signed
+// radix 16 digits do not need a reduction mod l first.
+fn scalarmult_base(s: array<u32, 8>) -> Ge {
+	var e: array<i32, 64>;
+	for (var i = 0u; i < 8u; i++) {
+		for (var j = 0u; j < 8u; j++) {
+			e[8u * i + j] = i32((s[i] >> (4u * j)) & 15u);
+		}
+	}
+	var carry = 0;
+	for (var i = 0u; i < 63u; i++) {
+		e[i] += carry;
+		carry = (e[i] + 8) >> 4u;
+		e[i] -= carry << 4u;
+	}
+	e[63] += carry;
+
+	var r = ge_identity();
+	for (var i = 1u; i < 64u; i += 2u) {
+		r = ge_add_niels(r, niels_entry(i / 2u, e[i]));
+	}
+	r = ge_double(r);
+	r = ge_double(r);
+	r = ge_double(r);
+	r = ge_double(r);
+	for (var i = 0u; i < 64u; i += 2u) {
+		r = ge_add_niels(r, niels_entry(i / 2u, e[i]));
+	}
+	return r;
+}
+
+fn derive_pubkey(key_material: array<u32, 8>) -> array<u32, 8> {
+	var hash = sha512_32bytes(key_material);
+	hash[0] &= 0xfffffff8u;
+	hash[7] &= 0x7fffffffu;
+	hash[7] |= 0x40000000u;
+	return ge_pack(scalarmult_base(hash));
+}
+
+fn search(thread: u32) {
+	var key_material = params.key_root;
+	key_material[0] += thread;
+	key_material[1] += select(0u, 1u, key_material[0] < thread);
+
+	let pubkey = derive_pubkey(key_material);
+
+	for (var i = 0u; i < params.prefix_len; i++) {
+		let shift = 8u * (i & 3u);
+		let byte = (pubkey[i / 4u] >> shift) & 0xffu;
+		let mask = (params.mask[i / 4u] >> shift) & 0xffu;
+		let req = (params.req[i / 4u] >> shift) & 0xffu;
+		if ((byte & mask) != req) {
+			return;
+		}
+	}
+	atomicMin(&result[0], thread);
+}
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn generate_pubkey(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) groups: vec3<u32>) {
+	search(id.x + id.y * groups.x * WORKGROUP_SIZE);
+}
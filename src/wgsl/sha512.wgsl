@@ -0,0 +1,170 @@
+// SHA-512 for a single 32 byte message, as used to expand an Ed25519 seed.
+// WGSL has no 64-bit integers, so every word is carried as a hi/lo pair.
+
+struct U64 {
+	hi: u32,
+	lo: u32,
+}
+
+const SHA512_K = array<u32, 160>(
+	0x428a2f98u, 0xd728ae22u, 0x71374491u, 0x23ef65cdu,
+	0xb5c0fbcfu, 0xec4d3b2fu, 0xe9b5dba5u, 0x8189dbbcu,
+	0x3956c25bu, 0xf348b538u, 0x59f111f1u, 0xb605d019u,
+	0x923f82a4u, 0xaf194f9bu, 0xab1c5ed5u, 0xda6d8118u,
+	0xd807aa98u, 0xa3030242u, 0x12835b01u, 0x45706fbeu,
+	0x243185beu, 0x4ee4b28cu, 0x550c7dc3u, 0xd5ffb4e2u,
+	0x72be5d74u, 0xf27b896fu, 0x80deb1feu, 0x3b1696b1u,
+	0x9bdc06a7u, 0x25c71235u, 0xc19bf174u, 0xcf692694u,
+	0xe49b69c1u, 0x9ef14ad2u, 0xefbe4786u, 0x384f25e3u,
+	0x0fc19dc6u, 0x8b8cd5b5u, 0x240ca1ccu, 0x77ac9c65u,
+	0x2de92c6fu, 0x592b0275u, 0x4a7484aau, 0x6ea6e483u,
+	0x5cb0a9dcu, 0xbd41fbd4u, 0x76f988dau, 0x831153b5u,
+	0x983e5152u, 0xee66dfabu, 0xa831c66du, 0x2db43210u,
+	0xb00327c8u, 0x98fb213fu, 0xbf597fc7u, 0xbeef0ee4u,
+	0xc6e00bf3u, 0x3da88fc2u, 0xd5a79147u, 0x930aa725u,
+	0x06ca6351u, 0xe003826fu, 0x14292967u, 0x0a0e6e70u,
+	0x27b70a85u, 0x46d22ffcu, 0x2e1b2138u, 0x5c26c926u,
+	0x4d2c6dfcu, 0x5ac42aedu, 0x53380d13u, 0x9d95b3dfu,
+	0x650a7354u, 0x8baf63deu, 0x766a0abbu, 0x3c77b2a8u,
+	0x81c2c92eu, 0x47edaee6u, 0x92722c85u, 0x1482353bu,
+	0xa2bfe8a1u, 0x4cf10364u, 0xa81a664bu, 0xbc423001u,
+	0xc24b8b70u, 0xd0f89791u, 0xc76c51a3u, 0x0654be30u,
+	0xd192e819u, 0xd6ef5218u, 0xd6990624u, 0x5565a910u,
+	0xf40e3585u, 0x5771202au, 0x106aa070u, 0x32bbd1b8u,
+	0x19a4c116u, 0xb8d2d0c8u, 0x1e376c08u, 0x5141ab53u,
+	0x2748774cu, 0xdf8eeb99u, 0x34b0bcb5u, 0xe19b48a8u,
+	0x391c0cb3u, 0xc5c95a63u, 0x4ed8aa4au, 0xe3418acbu,
+	0x5b9cca4fu, 0x7763e373u, 0x682e6ff3u, 0xd6b2b8a3u,
+	0x748f82eeu, 0x5defb2fcu, 0x78a5636fu, 0x43172f60u,
+	0x84c87814u, 0xa1f0ab72u, 0x8cc70208u, 0x1a6439ecu,
+	0x90befffau, 0x23631e28u, 0xa4506cebu, 0xde82bde9u,
+	0xbef9a3f7u, 0xb2c67915u, 0xc67178f2u, 0xe372532bu,
+	0xca273eceu, 0xea26619cu, 0xd186b8c7u, 0x21c0c207u,
+	0xeada7dd6u, 0xcde0eb1eu, 0xf57d4f7fu, 0xee6ed178u,
+	0x06f067aau, 0x72176fbau, 0x0a637dc5u, 0xa2c898a6u,
+	0x113f9804u, 0xbef90daeu, 0x1b710b35u, 0x131c471bu,
+	0x28db77f5u, 0x23047d84u, 0x32caab7bu, 0x40c72493u,
+	0x3c9ebe0au, 0x15c9bebcu, 0x431d67c4u, 0x9c100d4cu,
+	0x4cc5d4beu, 0xcb3e42b6u, 0x597f299cu, 0xfc657e2au,
+	0x5fcb6fabu, 0x3ad6faecu, 0x6c44198cu, 0x4a475817u,
+);
+
+const SHA512_IV = array<u32, 16>(
+	0x6a09e667u, 0xf3bcc908u, 0xbb67ae85u, 0x84caa73bu,
+	0x3c6ef372u, 0xfe94f82bu, 0xa54ff53au, 0x5f1d36f1u,
+	0x510e527fu, 0xade682d1u, 0x9b05688cu, 0x2b3e6c1fu,
+	0x1f83d9abu, 0xfb41bd6bu, 0x5be0cd19u, 0x137e2179u
+);
+
+fn u64_from(hi: u32, lo: u32) -> U64 {
+	var r: U64;
+	r.hi = hi;
+	r.lo = lo;
+	return r;
+}
+
+fn u64_add(a: U64, b: U64) -> U64 {
+	let lo = a.lo + b.lo;
+	return u64_from(a.hi + b.hi + select(0u, 1u, lo < a.lo), lo);
+}
+
+fn u64_xor(a: U64, b: U64) -> U64 {
+	return u64_from(a.hi ^ b.hi, a.lo ^ b.lo);
+}
+
+// n must be in 1..31
+fn u64_rotr_lo(x: U64, n: u32) -> U64 {
+	return u64_from((x.hi >> n) | (x.lo << (32u - n)), (x.lo >> n) | (x.hi << (32u - n)));
+}
+
+// n must be in 33..63
+fn u64_rotr_hi(x: U64, n: u32) -> U64 {
+	return u64_rotr_lo(u64_from(x.lo, x.hi), n - 32u);
+}
+
+// n must be in 1..31
+fn u64_shr(x: U64, n: u32) -> U64 {
+	return u64_from(x.hi >> n, (x.lo >> n) | (x.hi << (32u - n)));
+}
+
+fn sha512_s0(x: U64) -> U64 {
+	return u64_xor(u64_xor(u64_rotr_lo(x, 28u), u64_rotr_hi(x, 34u)), u64_rotr_hi(x, 39u));
+}
+
+fn sha512_s1(x: U64) -> U64 {
+	return u64_xor(u64_xor(u64_rotr_lo(x, 14u), u64_rotr_lo(x, 18u)), u64_rotr_hi(x, 41u));
+}
+
+fn sha512_s2(x: U64) -> U64 {
+	return u64_xor(u64_xor(u64_rotr_lo(x, 1u), u64_rotr_lo(x, 8u)), u64_shr(x, 7u));
+}
+
+fn sha512_s3(x: U64) -> U64 {
+	return u64_xor(u64_xor(u64_rotr_lo(x, 19u), u64_rotr_hi(x, 61u)), u64_shr(x, 6u));
+}
+
+fn sha512_k(i: u32) -> U64 {
+	return u64_from(SHA512_K[2u * i], SHA512_K[2u * i + 1u]);
+}
+
+// Hashes the 32 byte message given as eight little endian words and returns
+// the first 32 bytes of the digest in the same layout.
+fn sha512_32bytes(msg: array<u32, 8>) -> array<u32, 8> {
+	var w: array<U64, 16>;
+	for (var i = 0u; i < 4u; i++) {
+		// bytes are big endian within each 64-bit message word
+		let hi = msg[2u * i];
+		let lo = msg[2u * i + 1u];
+		w[i] = u64_from(
+			((hi & 0xffu) << 24u) | ((hi & 0xff00u) << 8u) | ((hi >> 8u) & 0xff00u) | (hi >> 24u),
+			((lo & 0xffu) << 24u) | ((lo & 0xff00u) << 8u) | ((lo >> 8u) & 0xff00u) | (lo >> 24u)
+		);
+	}
+	w[4] = u64_from(0x80000000u, 0u);
+	w[15] = u64_from(0u, 256u);
+
+	var h: array<U64, 8>;
+	for (var i = 0u; i < 8u; i++) {
+		h[i] = u64_from(SHA512_IV[2u * i], SHA512_IV[2u * i + 1u]);
+	}
+	var a = h[0];
+	var b = h[1];
+	var c = h[2];
+	var d = h[3];
+	var e = h[4];
+	var f = h[5];
+	var g = h[6];
+	var hh = h[7];
+
+	for (var i = 0u; i < 80u; i++) {
+		if (i >= 16u) {
+			let j = i & 15u;
+			w[j] = u64_add(u64_add(sha512_s3(w[(i - 2u) & 15u]), w[(i - 7u) & 15u]), u64_add(sha512_s2(w[(i - 15u) & 15u]), w[j]));
+		}
+		let ch = u64_from((e.hi & f.hi) ^ (~e.hi & g.hi), (e.lo & f.lo) ^ (~e.lo & g.lo));
+		let maj = u64_from((a.hi & b.hi) ^ (a.hi & c.hi) ^ (b.hi & c.hi), (a.lo & b.lo) ^ (a.lo & c.lo) ^ (b.lo & c.lo));
+		let t1 = u64_add(u64_add(u64_add(hh, sha512_s1(e)), u64_add(ch, sha512_k(i))), w[i & 15u]);
+		let t2 = u64_add(sha512_s0(a), maj);
+		hh = g;
+		g = f;
+		f = e;
+		e = u64_add(d, t1);
+		d = c;
+		c = b;
+		b = a;
+		a = u64_add(t1, t2);
+	}
+	h[0] = u64_add(h[0], a);
+	h[1] = u64_add(h[1], b);
+	h[2] = u64_add(h[2], c);
+	h[3] = u64_add(h[3], d);
+
+	var out: array<u32, 8>;
+	for (var i = 0u; i < 4u; i++) {
+		let hi = h[i].hi;
+		let lo = h[i].lo;
+		out[2u * i] = ((hi & 0xffu) << 24u) | ((hi & 0xff00u) << 8u) | ((hi >> 8u) & 0xff00u) | (hi >> 24u);
+		out[2u * i + 1u] = ((lo & 0xffu) << 24u) | ((lo & 0xff00u) << 8u) | ((lo >> 8u) & 0xff00u) | (lo >> 24u);
+	}
+	return out;
+}
@@ -0,0 +1,26 @@
+/// The BIP39 English wordlist, which Algorand mnemonics use as well. One word
+/// per line, sorted.
+const WORDLIST: &str = include_str!("wordlist.txt");
+
+pub fn words() -> Vec<&'static str> {
+    WORDLIST.lines().collect()
+}
+
+/// The index of `word` in the list
+pub fn index_of(word: &str) -> Option<usize> {
+    words().binary_search(&word).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wordlist() {
+        let words = words();
+        assert_eq!(words.len(), 2048);
+        assert_eq!(index_of("abandon"), Some(0));
+        assert_eq!(index_of("zoo"), Some(2047));
+        assert_eq!(index_of("zzz"), None);
+    }
+}